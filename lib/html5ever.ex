@@ -19,14 +19,139 @@ defmodule Html5ever do
 
       config :html5ever, Html5ever, build_from_source: true
 
+  The functions return `{:error, error}` when they fail, where `error` is
+  an `Html5ever.Error` exception with the reason and a message.
+
   This project is possible thanks to [Rustler](https://hexdocs.pm/rustler).
   """
 
+  @parse_opts [
+    attributes_as_maps: false,
+    parse_errors: false,
+    quirks_mode: false,
+    source_positions: false,
+    detect_encoding: false,
+    transport_encoding: nil,
+    lossy_utf8: false,
+    namespaces: false,
+    template_contents: false,
+    max_nodes: nil,
+    max_depth: nil,
+    max_attributes_per_element: nil,
+    max_input_bytes: nil
+  ]
+
+  @sanitize_policy [
+    tags: ~w(
+      a abbr b blockquote br code dd del dl dt em h1 h2 h3 h4 h5 h6 hr i img ins kbd li ol p pre
+      q s small span strong sub sup table tbody td tfoot th thead tr u ul
+    ),
+    attributes: %{
+      "a" => ["href", "title"],
+      "abbr" => ["title"],
+      "blockquote" => ["cite"],
+      "img" => ["src", "alt", "title", "width", "height"],
+      "q" => ["cite"],
+      "td" => ["colspan", "rowspan"],
+      "th" => ["colspan", "rowspan", "scope"]
+    },
+    url_schemes: ["http", "https", "mailto"],
+    comments: false,
+    drop_content: ~w(
+      script style template iframe object embed noscript noembed noframes title textarea
+      select xmp
+    )
+  ]
+
   @doc """
   Parses an HTML document from a string.
 
   This returns a list of tuples representing the HTML tree.
 
+  ## Options
+
+    * `:attributes_as_maps` - when `true`, the attributes are returned as maps.
+      See `parse_with_attributes_as_maps/1`. Defaults to `false`.
+
+    * `:parse_errors` - when `true`, the parse errors found in the document
+      are collected and returned in the `:errors` key of an info map, making
+      the result a `{:ok, tree, info}` tuple. Each error is a map with the
+      `:message` and the `:line` where the error was found. Defaults to `false`.
+
+    * `:quirks_mode` - when `true`, the quirks mode detected for the document
+      is returned in the `:quirks_mode` key of the info map. It is one of
+      `:no_quirks`, `:limited_quirks` or `:quirks`. The flat results always
+      include it in the document node, so this option has no effect there.
+      Defaults to `false`.
+
+    * `:source_positions` - when `true`, each node carries the line of the
+      source where the parser created it. For elements this is the line where
+      the start tag ends. In the nested results, elements, comments, doctypes
+      and processing instructions get an extra tuple element with a map of
+      metadata, like `{"p", [], ["Hello"], %{line: 2}}`. Text nodes are kept
+      as strings, without metadata. In the flat results, every node except the
+      document gets a `:line` key. Byte offsets are not available, because
+      html5ever only tracks lines. Defaults to `false`.
+
+    * `:detect_encoding` - when `true`, the input is treated as bytes in any
+      encoding, instead of UTF-8. The encoding is detected by looking for a
+      byte order mark, then the `:transport_encoding`, and then the
+      `<meta charset>` declarations in the first 1024 bytes of the document, as
+      the HTML spec describes. If none is found, the input is read as UTF-8 when
+      it is valid, or as "windows-1252" otherwise. The input is decoded to UTF-8
+      before being parsed, and the name of the detected encoding, like
+      `"Shift_JIS"`, is returned in the `:encoding` key of the info map.
+      Defaults to `false`.
+
+    * `:transport_encoding` - the charset declared by the transport layer,
+      like the one from the `Content-Type` header of an HTTP response. It takes
+      precedence over the `<meta charset>` declarations. Setting it enables
+      `:detect_encoding`. Defaults to `nil`.
+
+    * `:lossy_utf8` - when `true`, invalid UTF-8 sequences in the input are
+      replaced by the replacement character (U+FFFD), like browsers do, instead
+      of returning an error. Defaults to `false`.
+
+    * `:namespaces` - when `true`, the namespaces of elements and attributes
+      are kept. In the nested results, element names become `{namespace, name}`
+      tuples, like `{:svg, "title"}`. In the flat results, the name is kept and
+      the element gets a `:namespace` key. Attributes without a namespace keep
+      their names as strings, and the others are named by tuples as well, like
+      `{:xlink, "href"}`. The namespaces are the atoms `:html`, `:svg`, `:mathml`,
+      `:xlink`, `:xml` and `:xmlns`, or the URI of any other namespace.
+      Defaults to `false`.
+
+    * `:template_contents` - when `true`, the contents of `<template>` elements
+      are kept. By default they are ignored, because the parser puts them in a
+      separated document fragment. In the nested results, they are returned as
+      the children of the template element. In the flat results, the template
+      element gets a `:content` key with the ID of a node of the type
+      `:document_fragment`, which holds the contents. Defaults to `false`.
+
+  The following options limit the resources used to parse untrusted input.
+  When one of them is exceeded, the parsing stops and `{:error, error}` is
+  returned, with a reason like `{:limit_exceeded, :max_nodes, 1000}`. All of
+  them default to `nil`, which means no limit.
+
+  The limits are checked after every 64 KB of input, or after every chunk
+  given to `parser_feed/2`, so the parser can go past them by the nodes
+  created for the rest of that input before it stops.
+
+    * `:max_input_bytes` - the maximum size of the input, in bytes.
+
+    * `:max_nodes` - the maximum number of nodes created by the parser, not
+      counting the document. This includes the elements the parser adds by
+      itself, like `<html>`, `<head>` and `<body>`.
+
+    * `:max_depth` - the maximum depth of the tree, where the children of the
+      document have a depth of `1`. Text nodes count as well. The depth is
+      checked while the tree is built, so it is exceeded by a node inserted
+      too deep, even when the parser moves it up later, like it does with
+      misnested formatting elements.
+
+    * `:max_attributes_per_element` - the maximum number of attributes
+      of an element.
+
   ## Example
 
       iex> Html5ever.parse("<!doctype html><html><body><h1>Hello world</h1></body></html>")
@@ -37,12 +162,12 @@ defmodule Html5ever do
        ]}
 
   """
-  def parse(html) when is_binary(html) do
-    Html5ever.Native.parse(html, false)
+  def parse(html, opts \\ []) when is_binary(html) and is_list(opts) do
+    Html5ever.Native.parse(html, native_opts(opts))
   end
 
   @doc """
-  Same as `parse/1`, but with attributes as maps.
+  Same as `parse/2`, but with attributes as maps.
 
   This is going to remove duplicated attributes, keeping the ones
   that appear first.
@@ -60,7 +185,7 @@ defmodule Html5ever do
 
   """
   def parse_with_attributes_as_maps(html) when is_binary(html) do
-    Html5ever.Native.parse(html, true)
+    parse(html, attributes_as_maps: true)
   end
 
   @doc """
@@ -68,14 +193,17 @@ defmodule Html5ever do
 
   The map contains the document structure.
 
+  It accepts the same options as `parse/2`. The information
+  requested by the options is added to the returned map.
+
   ## Example
 
       iex> Html5ever.flat_parse("<!doctype html><html><body><h1>Hello world</h1></body></html>")
       {:ok,
        %{
          nodes: %{
-           0 => %{id: 0, parent: nil, type: :document, children: [1, 2]},
-           1 => %{id: 1, parent: 0, type: :doctype, name: "html"},
+           0 => %{id: 0, parent: nil, type: :document, children: [1, 2], quirks_mode: :no_quirks},
+           1 => %{id: 1, parent: 0, type: :doctype, name: "html", public_id: "", system_id: ""},
            2 => %{
              attrs: [],
              children: [3, 4],
@@ -114,17 +242,720 @@ defmodule Html5ever do
        }}
 
   """
-  def flat_parse(html) when is_binary(html) do
-    Html5ever.Native.flat_parse(html, false)
+  def flat_parse(html, opts \\ []) when is_binary(html) and is_list(opts) do
+    Html5ever.Native.flat_parse(html, native_opts(opts))
   end
 
   @doc """
-  Same as `flat_parse/1`, but with attributes as maps.
+  Same as `flat_parse/2`, but with attributes as maps.
 
   This is going to remove duplicated attributes, keeping the ones
   that appear first.
   """
   def flat_parse_with_attributes_as_maps(html) when is_binary(html) do
-    Html5ever.Native.flat_parse(html, true)
+    flat_parse(html, attributes_as_maps: true)
+  end
+
+  @doc """
+  Parses an HTML fragment from a string, using `context` as the
+  name of the element that would be the parent of the fragment.
+
+  Differently from `parse/2`, the fragment is not wrapped in
+  `html`, `head` and `body` elements, and the content is parsed
+  as it would be inside the context element. So a table row can
+  be parsed with `"tbody"` as the context.
+
+  The context is an element in the HTML namespace, except for
+  `"svg"` and `"math"`, which are in the SVG and MathML namespaces.
+
+  This returns a list of tuples representing the nodes of the fragment.
+  It accepts the same options as `parse/2`.
+
+  ## Example
+
+      iex> Html5ever.parse_fragment("<tr><td>Hello world</td></tr>", "tbody")
+      {:ok, [{"tr", [], [{"td", [], ["Hello world"]}]}]}
+
+  """
+  def parse_fragment(html, context, opts \\ [])
+      when is_binary(html) and is_binary(context) and is_list(opts) do
+    Html5ever.Native.parse_fragment(html, context, native_opts(opts))
+  end
+
+  @doc """
+  Same as `parse_fragment/3`, but returns a map like `flat_parse/2`.
+
+  The root is a document node that holds the nodes of the fragment.
+  It accepts the same options as `parse/2`.
+
+  ## Example
+
+      iex> Html5ever.flat_parse_fragment("<td>Hello</td>", "tr")
+      {:ok,
+       %{
+         nodes: %{
+           0 => %{id: 0, parent: nil, type: :document, children: [3], quirks_mode: :no_quirks},
+           3 => %{attrs: [], children: [4], id: 3, name: "td", parent: 0, type: :element},
+           4 => %{contents: "Hello", id: 4, parent: 3, type: :text}
+         },
+         root: 0
+       }}
+
+  """
+  def flat_parse_fragment(html, context, opts \\ [])
+      when is_binary(html) and is_binary(context) and is_list(opts) do
+    Html5ever.Native.flat_parse_fragment(html, context, native_opts(opts))
+  end
+
+  @doc """
+  Parses an XML document from a string, like RSS and Atom feeds,
+  sitemaps or XHTML documents.
+
+  This returns a list of tuples in the same format as `parse/2`, with
+  processing instructions as `{:pi, target, contents}`. The XML declaration,
+  like `<?xml version="1.0"?>`, is returned as a processing instruction too.
+  It accepts the same options as `parse/2`.
+
+  The names of elements and attributes are returned without their prefixes,
+  so the `:namespaces` option is useful to tell apart elements like `<link>`
+  and `<atom:link>` in a feed. The `xmlns` attributes that declare the
+  namespaces are not returned.
+
+  The lines are not tracked by the XML parser, so the `:source_positions`
+  option returns `1` as the line of all the nodes.
+
+  ## Examples
+
+      iex> Html5ever.parse_xml(~s(<feed><entry id="1">Hello</entry></feed>))
+      {:ok, [{"feed", [], [{"entry", [{"id", "1"}], ["Hello"]}]}]}
+
+      iex> xml = ~s(<rss xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:creator>Me</dc:creator></rss>)
+      iex> Html5ever.parse_xml(xml, namespaces: true)
+      {:ok,
+       [{{nil, "rss"}, [], [{{"http://purl.org/dc/elements/1.1/", "creator"}, [], ["Me"]}]}]}
+
+  """
+  def parse_xml(xml, opts \\ []) when is_binary(xml) and is_list(opts) do
+    Html5ever.Native.parse_xml(xml, native_opts(opts))
+  end
+
+  @doc """
+  Same as `parse_xml/2`, but returns a map like `flat_parse/2`.
+
+  ## Example
+
+      iex> Html5ever.flat_parse_xml("<urlset><url>/a</url></urlset>")
+      {:ok,
+       %{
+         nodes: %{
+           0 => %{id: 0, parent: nil, type: :document, children: [1], quirks_mode: :no_quirks},
+           1 => %{attrs: [], children: [2], id: 1, name: "urlset", parent: 0, type: :element},
+           2 => %{attrs: [], children: [3], id: 2, name: "url", parent: 1, type: :element},
+           3 => %{contents: "/a", id: 3, parent: 2, type: :text}
+         },
+         root: 0
+       }}
+
+  """
+  def flat_parse_xml(xml, opts \\ []) when is_binary(xml) and is_list(opts) do
+    Html5ever.Native.flat_parse_xml(xml, native_opts(opts))
+  end
+
+  @doc """
+  Tokenizes an HTML document from a string, without building a tree.
+
+  This returns the tokens in the order they were written, so there are no
+  implied elements, like `<tbody>`, and misnested tags are not fixed. The
+  tokens are:
+
+    * `{:start_tag, name, attributes, self_closing}`
+    * `{:end_tag, name}`
+    * `{:characters, text}`
+    * `{:comment, contents}`
+    * `{:doctype, name, public_id, system_id, force_quirks}`, where the name
+      and the IDs are `nil` when they are missing
+    * `:eof`, which is always the last token
+
+  The contents of elements like `<script>`, `<style>` and `<textarea>` are
+  returned as characters, like the parser does for HTML elements. Inside
+  `<svg>` and `<math>` they are tokenized as markup, and CDATA sections are
+  returned as characters, like in the parser.
+
+  Without a tree, the elements that are open are not known exactly, so the
+  tokens can still differ from the ones the parser sees in a few cases:
+
+    * the contents of `<foreignObject>`, `<desc>` and `<title>` in SVG, and
+      of `<annotation-xml>` and the text elements in MathML, are treated
+      as foreign content, while the parser treats them as HTML
+
+    * `<svg>` and `<math>` elements that are closed implicitly by the
+      parser, without an end tag, are considered open until the end
+
+  It accepts the options of `parse/2` to decode the input, along with
+  `:attributes_as_maps`. With the `:parse_errors` option, the errors are
+  returned in place as `{:parse_error, %{line: line, message: message}}`.
+
+  ## Example
+
+      iex> Html5ever.tokenize("<table><tr><td class=a>Hi</b></table>")
+      {:ok,
+       [
+         {:start_tag, "table", [], false},
+         {:start_tag, "tr", [], false},
+         {:start_tag, "td", [{"class", "a"}], false},
+         {:characters, "Hi"},
+         {:end_tag, "b"},
+         {:end_tag, "table"},
+         :eof
+       ]}
+
+  """
+  def tokenize(html, opts \\ []) when is_binary(html) and is_list(opts) do
+    Html5ever.Native.tokenize(html, native_opts(opts))
+  end
+
+  @doc """
+  Serializes a tree in the format returned by `parse/2` back to HTML.
+
+  The tree can be a single node or a list of nodes. Text is escaped,
+  void elements like `<br>` have no end tags, and the contents of
+  raw text elements like `<script>` are kept as they are, following
+  the serialization algorithm of the HTML spec.
+
+  Attributes can be lists or maps, and nodes may carry the metadata
+  added by the `:source_positions` option. Element names can also be
+  `{namespace, name}` tuples, like the ones returned with the `:namespaces`
+  option. Without a namespace, elements inside `<svg>` and `<math>`
+  are in the SVG and MathML namespaces, like when they are parsed.
+
+  Returns `{:error, error}` in case the tree has an invalid node.
+
+  ## Example
+
+      iex> Html5ever.serialize([{"p", [{"class", "greeting"}], ["Hello & ", {"br", [], []}, "world"]}])
+      {:ok, ~s(<p class="greeting">Hello &amp; <br>world</p>)}
+
+  """
+  def serialize(tree) when is_list(tree) or is_tuple(tree) or is_binary(tree) do
+    Html5ever.Native.serialize(tree)
+  end
+
+  @doc """
+  Serializes a map in the format returned by `flat_parse/2` back to HTML.
+
+  The serialization starts from the node with the given `id`, which defaults
+  to the `:root` of the map. Only the node and its descendants are serialized,
+  so the map can have nodes that are not part of the tree anymore. Documents
+  and document fragments have only their children serialized.
+
+  The nodes are found by the IDs in the `:children` of their parents, which
+  makes the map easy to edit before the serialization. Elements without the
+  `:namespace` key are in the HTML namespace, unless they are inside `<svg>`
+  or `<math>` elements, and the `:content` of templates is serialized as
+  their children.
+
+  Returns `{:error, error}` in case a node is invalid or cannot be found.
+
+  ## Example
+
+      iex> {:ok, flat} = Html5ever.flat_parse("<p>Hello <b>world</b></p>")
+      iex> Html5ever.flat_serialize(flat)
+      {:ok, "<html><head></head><body><p>Hello <b>world</b></p></body></html>"}
+      iex> Html5ever.flat_serialize(flat, 4)
+      {:ok, "<p>Hello <b>world</b></p>"}
+
+  """
+  def flat_serialize(%{nodes: nodes, root: root}, id \\ nil) when is_map(nodes) do
+    Html5ever.Native.flat_serialize(nodes, id || root)
+  end
+
+  @doc """
+  Selects the elements matched by a CSS selector.
+
+  The first argument can be an HTML document as a string, or a document
+  returned by `parse_to_resource/2`. For a string, the matched elements
+  are returned as trees in the format of `parse/2`, which accepts the same
+  options. For a document, the IDs of the matched elements are returned.
+  In both cases the elements are in the order they appear in the document.
+
+  The selector can be a list of selectors separated by commas. The following
+  selectors are supported:
+
+    * type, universal, class and ID selectors, like `p`, `*`, `.title` and `#main`
+
+    * attribute selectors, like `[href]`, `[lang|=en]` and `[type="text" i]`,
+      with all the operators: `=`, `~=`, `|=`, `^=`, `$=` and `*=`
+
+    * the descendant (` `), child (`>`), next sibling (`+`) and subsequent
+      sibling (`~`) combinators
+
+    * the `:nth-child()`, `:nth-last-child()`, `:nth-of-type()`,
+      `:nth-last-of-type()`, `:first-child`, `:last-child`, `:only-child`,
+      `:first-of-type`, `:last-of-type`, `:only-of-type`, `:root` and
+      `:empty` pseudo-classes
+
+    * the `:not()`, `:is()`, `:where()` and `:has()` pseudo-classes, which
+      accept lists of complex selectors. `:has()` accepts relative selectors,
+      like `:has(> img)`
+
+  These pseudo-classes can be nested up to 32 times.
+
+  Returns `{:error, error}` in case the selector is invalid or not supported.
+
+  ## Example
+
+      iex> Html5ever.select("<ul><li>One</li><li class=two>Two</li></ul>", "ul > li.two")
+      {:ok, [{"li", [{"class", "two"}], ["Two"]}]}
+
+  """
+  def select(html_or_document, selector, opts \\ [])
+
+  def select(document, selector, []) when is_reference(document) and is_binary(selector) do
+    Html5ever.Native.resource_select(document, selector)
+  end
+
+  def select(html, selector, opts)
+      when is_binary(html) and is_binary(selector) and is_list(opts) do
+    Html5ever.Native.select(html, selector, native_opts(opts))
+  end
+
+  @doc """
+  Evaluates an XPath 1.0 expression, with the document as the context node.
+
+  Like `select/3`, the first argument can be an HTML document as a string,
+  or a document returned by `parse_to_resource/2`. The result depends on
+  the type of the expression:
+
+    * node-sets are lists of nodes in the order they appear in the document.
+      For a string, the nodes are in the format of `parse/2`, which accepts
+      the same options, and attributes are `{name, value}` tuples. For a
+      document, the nodes are their IDs, and attributes are `{id, name}`
+      tuples, with the ID of their element
+
+    * numbers are floats. Since the BEAM has no floats for them, NaN and the
+      infinities are returned as the atoms `:nan`, `:infinity` and `:neg_infinity`
+
+    * strings and booleans are returned as they are
+
+  All the axes except `namespace`, and all the functions of the core library
+  are supported. Variables and namespace prefixes are not. The names of HTML
+  elements and attributes are matched case-insensitively, like browsers do,
+  and the doctype is not part of the tree. The expressions in parentheses,
+  predicates and function arguments can be nested up to 32 times, where each
+  operator of a chain like `//a | //b | //c` counts as a level as well.
+
+  Returns `{:error, error}` in case the expression is invalid or not supported.
+
+  ## Examples
+
+      iex> Html5ever.xpath("<ul><li>One</li><li>Two</li></ul>", "//li[2]/text()")
+      {:ok, ["Two"]}
+
+      iex> Html5ever.xpath("<ul><li>One</li><li>Two</li></ul>", "count(//li)")
+      {:ok, 2.0}
+
+  """
+  def xpath(html_or_document, expression, opts \\ [])
+
+  def xpath(document, expression, []) when is_reference(document) and is_binary(expression) do
+    Html5ever.Native.resource_xpath(document, expression)
+  end
+
+  def xpath(html, expression, opts)
+      when is_binary(html) and is_binary(expression) and is_list(opts) do
+    Html5ever.Native.xpath(html, expression, native_opts(opts))
+  end
+
+  @doc """
+  Returns the visible text of an HTML document, like the `innerText`
+  property of the DOM.
+
+  The first argument can be an HTML document as a string, with the same
+  options as `parse/2`, or a document returned by `parse_to_resource/2`,
+  with the ID of the node to extract the text from. The whole document
+  is used when the ID is omitted.
+
+  The text of `<head>`, `<script>`, `<style>`, `<template>` and other
+  elements that are not rendered is skipped, along with comments. Runs of
+  whitespace are collapsed into a single space, except in `<pre>` and
+  `<textarea>`, and block-level elements are separated by newlines: one
+  for most of them, like `<div>` and `<li>`, and two for `<p>`. `<br>`
+  is a newline, and cells in table rows are separated by tabs.
+
+  ## Example
+
+      iex> Html5ever.text_content("<h1>Hello</h1><p>big   <b>world</b></p><script>x()</script>")
+      {:ok, "Hello\\n\\nbig world"}
+
+  """
+  def text_content(html_or_document, opts_or_id \\ [])
+
+  def text_content(document, []) when is_reference(document) do
+    Html5ever.Native.resource_text_content(document, 0)
+  end
+
+  def text_content(document, id) when is_reference(document) and is_integer(id) and id >= 0 do
+    Html5ever.Native.resource_text_content(document, id)
+  end
+
+  def text_content(html, opts) when is_binary(html) and is_list(opts) do
+    Html5ever.Native.text_content(html, native_opts(opts))
+  end
+
+  @doc """
+  Returns the URLs referenced by an HTML document, resolved against
+  its base URL, as a list of maps with the `:element`, the `:attribute`
+  and the `:url`, in document order.
+
+  The base URL of the document is the `href` of its first `<base>` element,
+  resolved against `base_url`, which must be absolute. When there is no
+  `<base>` element, `base_url` is used.
+
+  The URLs are taken from:
+
+    * the `href` of `<a>`, `<area>` and `<link>`
+
+    * the `src` of `<img>`, `<script>`, `<iframe>`, `<frame>`, `<embed>`,
+      `<source>`, `<video>`, `<audio>`, `<track>` and `<input>`
+
+    * each candidate of the `srcset` of `<img>` and `<source>`
+
+    * the `poster` of `<video>`, the `data` of `<object>`, the `action`
+      of `<form>` and the `formaction` of `<button>` and `<input>`
+
+    * the `content` of `<meta http-equiv="refresh">`, like `5; url=/next`
+
+    * the `url()` functions in the `style` attribute of any element
+
+  The URLs that cannot be resolved, because they are invalid, are skipped.
+  Other schemes, like `mailto:` and `data:`, are kept, and so are
+  repeated URLs. The options are the same of `parse/2`, and the contents
+  of templates are included only with `:template_contents`.
+
+  ## Example
+
+      iex> Html5ever.extract_links(
+      ...>   ~s(<base href="/docs/"><a href="intro">Intro</a><img srcset="a.png 1x, /b.png 2x">),
+      ...>   "https://example.com/"
+      ...> )
+      {:ok,
+       [
+         %{element: "a", attribute: "href", url: "https://example.com/docs/intro"},
+         %{element: "img", attribute: "srcset", url: "https://example.com/docs/a.png"},
+         %{element: "img", attribute: "srcset", url: "https://example.com/b.png"}
+       ]}
+
+  """
+  def extract_links(html, base_url, opts \\ [])
+      when is_binary(html) and is_binary(base_url) and is_list(opts) do
+    Html5ever.Native.extract_links(html, base_url, native_opts(opts))
+  end
+
+  @doc """
+  Returns the metadata of an HTML document, like the one used for link
+  previews, without building the tree in Elixir. It is a map with:
+
+    * `:title` - the text of the first `<title>`, with its whitespace
+      collapsed, or `nil` when there is none
+
+    * `:lang` - the `lang` attribute of the `<html>` element, or `nil`
+
+    * `:charset` - the encoding declared by `<meta charset>` or by
+      `<meta http-equiv="content-type">`, with its name in the Encoding
+      Standard, like `"UTF-8"`, or `nil` when it is unknown or missing
+
+    * `:canonical` - the `href` of the first `<link rel="canonical">`,
+      as written, or `nil`
+
+    * `:meta` - the `content` of the `<meta>` elements as a list of tuples
+      with their `name` or `property`, which is lowercase, in document order.
+      It includes the ones of Open Graph, like `"og:title"`, and Twitter
+      cards, like `"twitter:card"`, which can be repeated
+
+    * `:json_ld` - the contents of the `<script type="application/ld+json">`
+      elements, as strings that are not decoded
+
+  The options are the same of `parse/2`, but only the ones about the input
+  and the limits are used.
+
+  ## Example
+
+      iex> Html5ever.metadata("""
+      ...> <html lang="en"><head><meta charset="utf-8"><title>Hello</title>
+      ...> <meta property="og:title" content="Hello"><meta name="description" content="A page">
+      ...> <link rel="canonical" href="https://example.com/hello"></head></html>
+      ...> """)
+      {:ok,
+       %{
+         title: "Hello",
+         lang: "en",
+         charset: "UTF-8",
+         canonical: "https://example.com/hello",
+         meta: [{"og:title", "Hello"}, {"description", "A page"}],
+         json_ld: []
+       }}
+
+  """
+  def metadata(html, opts \\ []) when is_binary(html) and is_list(opts) do
+    Html5ever.Native.metadata(html, native_opts(opts))
+  end
+
+  @doc """
+  Sanitizes an HTML fragment with an allowlist policy, returning it as HTML.
+
+  The HTML is parsed as the contents of a `<body>` element, like
+  `parse_fragment/3` does, and then the nodes not allowed by the policy are
+  removed, so the result is what a browser would render from the input.
+  The policy is a keyword list with the following keys:
+
+    * `:tags` - the names of the elements that are kept. Other elements are
+      replaced by their contents, unless they are in `:drop_content`
+
+    * `:attributes` - a map with the names of the attributes that are kept, by
+      element name. The attributes under `"*"` are kept for all the elements
+
+    * `:url_schemes` - the schemes allowed in URL attributes, like `href` and
+      `src`. Attributes with other schemes are removed. Relative URLs are
+      always allowed
+
+    * `:comments` - whether comments are kept
+
+    * `:drop_content` - the names of the elements that are removed along
+      with their contents
+
+  The keys that are not given take their default values. They allow basic
+  formatting elements, like `<p>`, `<b>` and `<ul>`, links with their
+  `href` and `title`, and images, with the `http`, `https` and `mailto`
+  schemes. Comments are removed, and so are `<script>`, `<style>`,
+  `<iframe>` and other elements whose contents are not text to display.
+
+  The names of the elements and attributes are lowercase. The options are
+  the same of `parse/2`, but only the ones about the input are used.
+
+  ## Example
+
+      iex> Html5ever.sanitize(~s(<p onclick="steal()">Hello <a href="javascript:steal()">world</a></p>))
+      {:ok, "<p>Hello <a>world</a></p>"}
+
+  """
+  def sanitize(html, policy \\ [], opts \\ [])
+      when is_binary(html) and is_list(policy) and is_list(opts) do
+    Html5ever.Native.sanitize(html, native_policy(policy), native_opts(opts))
+  end
+
+  @doc """
+  Sanitizes an HTML fragment like `sanitize/3`, but returns a list
+  of nodes in the format of `parse/2`, which accepts the same options.
+
+  ## Example
+
+      iex> Html5ever.sanitize_to_tree("<p>Hello <script>alert()</script><b>world</b></p>")
+      {:ok, [{"p", [], ["Hello ", {"b", [], ["world"]}]}]}
+
+  """
+  def sanitize_to_tree(html, policy \\ [], opts \\ [])
+      when is_binary(html) and is_list(policy) and is_list(opts) do
+    Html5ever.Native.sanitize_to_tree(html, native_policy(policy), native_opts(opts))
+  end
+
+  @doc """
+  Rewrites a document with a list of rules, returning it as HTML.
+
+  Each rule is a tuple with a CSS selector, like the ones of `select/3`, and
+  a list of actions applied to the elements it matches. The actions are:
+
+    * `{:set_attribute, name, value}` - sets the attribute, replacing its value
+      if the element already has it
+
+    * `{:remove_attribute, name}` - removes the attribute
+
+    * `{:set_inner_html, html}` - replaces the children of the element
+
+    * `{:set_inner_text, text}` - replaces the children of the element with
+      the text, which is escaped when serialized
+
+    * `{:prepend, html}` - inserts the HTML before the first child
+
+    * `{:append, html}` - inserts the HTML after the last child
+
+    * `:remove` - removes the element with its contents
+
+  The document is rewritten in a single pass over its tokens, without
+  building a tree, and the source that is not changed is copied as it is.
+  No `<html>`, `<head>`, `<body>` or `<tbody>` elements are added, and the
+  doctype and the comments are kept verbatim. The start tags of the elements
+  whose attributes change are serialized again, with the values in double
+  quotes.
+
+  The rules are matched against the elements that are open when a start tag
+  is found, so the content inserted by the actions is not matched by them.
+  The HTML of the actions is written as it is, without being parsed, so it is
+  parsed by browsers in the context of the element, including in SVG and
+  MathML. The actions that change the contents have no effect on void
+  elements, like `<img>`, and on self-closing SVG and MathML elements.
+
+  Since the elements after the matched ones are not known yet, the selectors
+  that depend on them, like `:has()`, `:empty`, `:last-child`,
+  `:only-child` and `:nth-last-child()`, are not supported and return
+  `{:invalid_selector, message}`. The end tags implied by the HTML parsing
+  rules are handled for the common elements, like `<p>`, `<li>` and the
+  table cells.
+
+  The options about the input of `parse/2`, like `:detect_encoding` and
+  `:max_input_bytes`, are accepted.
+
+  ## Example
+
+      iex> Html5ever.rewrite(
+      ...>   ~s(<a href="/a" ping="/track">A</a><script>track()</script>),
+      ...>   [
+      ...>     {"a[ping]", [{:remove_attribute, "ping"}, {:set_attribute, "rel", "noopener"}]},
+      ...>     {"script", [:remove]}
+      ...>   ]
+      ...> )
+      {:ok, ~s(<a href="/a" rel="noopener">A</a>)}
+
+  """
+  def rewrite(html, rules, opts \\ [])
+      when is_binary(html) and is_list(rules) and is_list(opts) do
+    Html5ever.Native.rewrite(html, rules, native_opts(opts))
+  end
+
+  @doc """
+  Starts a parser session, which parses a document that arrives in chunks.
+
+  The chunks are given to `parser_feed/2`, which parses them as soon as they
+  arrive, so the whole document is never kept in a single binary. Then
+  `parser_finish/1` returns the document in the same format as `parse/2`,
+  which accepts the same options.
+
+  Characters split between chunks are handled. When the encoding is detected,
+  with the `:detect_encoding` or `:transport_encoding` options, the first
+  1024 bytes are kept until the encoding is known, and then they are parsed.
+
+  A session is used by one process at a time, and it is freed when
+  the reference is garbage collected.
+
+  ## Example
+
+      iex> {:ok, parser} = Html5ever.parser_new()
+      iex> Html5ever.parser_feed(parser, "<p>Hello <b>wor")
+      :ok
+      iex> Html5ever.parser_feed(parser, "ld</b></p>")
+      :ok
+      iex> Html5ever.parser_finish(parser)
+      {:ok,
+       [
+         {"html", [],
+          [{"head", [], []}, {"body", [], [{"p", [], ["Hello ", {"b", [], ["world"]}]}]}]}
+       ]}
+
+  """
+  def parser_new(opts \\ []) when is_list(opts) do
+    Html5ever.Native.parser_new(native_opts(opts))
+  end
+
+  @doc """
+  Parses a chunk of a document in a session started by `parser_new/1`.
+
+  Returns `:ok`, or `{:error, error}` in case the chunk is not valid
+  UTF-8, when the encoding is not detected. In that case the chunk is
+  ignored, and the session can still be used.
+  """
+  def parser_feed(parser, chunk) when is_reference(parser) and is_binary(chunk) do
+    Html5ever.Native.parser_feed(parser, chunk)
+  end
+
+  @doc """
+  Finishes a session started by `parser_new/1`, returning the document
+  like `parse/2` does.
+
+  The session cannot be used after it is finished.
+  """
+  def parser_finish(parser) when is_reference(parser) do
+    Html5ever.Native.parser_finish(parser)
+  end
+
+  @doc """
+  Parses an HTML document from a string and keeps it in memory,
+  returning a reference to it.
+
+  The document is not converted to Elixir terms. Instead, it can be
+  read by passing the reference to `query/2`, `text/2`, `attributes/2`
+  and `serialize/2`, which is much faster for big documents when only
+  a few parts of them are needed. The document is freed when the
+  reference is garbage collected.
+
+  The nodes are identified by the same IDs returned by `flat_parse/2`,
+  and the document node has the ID `0`. It accepts the same options
+  as `parse/2`, but the information about the parsing is not returned.
+
+  ## Example
+
+      iex> {:ok, document} = Html5ever.parse_to_resource("<p>Hello <a href='/world'>world</a></p>")
+      iex> {:ok, [id]} = Html5ever.query(document, "a")
+      iex> Html5ever.attributes(document, id)
+      {:ok, [{"href", "/world"}]}
+      iex> Html5ever.text(document, id)
+      {:ok, "world"}
+
+  """
+  def parse_to_resource(html, opts \\ []) when is_binary(html) and is_list(opts) do
+    Html5ever.Native.parse_to_resource(html, native_opts(opts))
+  end
+
+  @doc """
+  Returns the IDs of the elements with the given name in a
+  document returned by `parse_to_resource/2`.
+
+  The IDs are in the order the elements appear in the document.
+  """
+  def query(document, name) when is_reference(document) and is_binary(name) do
+    Html5ever.Native.resource_query(document, name)
+  end
+
+  @doc """
+  Returns the text of a node in a document returned by `parse_to_resource/2`.
+
+  For elements and documents, this is the text of all their descendants,
+  like the `textContent` property of the DOM. For comments, it is their
+  contents.
+  """
+  def text(document, id) when is_reference(document) and is_integer(id) and id >= 0 do
+    Html5ever.Native.resource_text(document, id)
+  end
+
+  @doc """
+  Returns the attributes of an element in a document returned by
+  `parse_to_resource/2`.
+
+  The attributes are a list, or a map when the document was parsed
+  with the `:attributes_as_maps` option.
+  """
+  def attributes(document, id) when is_reference(document) and is_integer(id) and id >= 0 do
+    Html5ever.Native.resource_attributes(document, id)
+  end
+
+  @doc """
+  Serializes a node of a document returned by `parse_to_resource/2`
+  to HTML, like `serialize/1` does.
+
+  The whole document is serialized with the ID `0`.
+  """
+  def serialize(document, id) when is_reference(document) and is_integer(id) and id >= 0 do
+    Html5ever.Native.resource_serialize(document, id)
+  end
+
+  defp native_opts(opts) do
+    opts
+    |> Keyword.validate!(@parse_opts)
+    |> Map.new()
+  end
+
+  defp native_policy(policy) do
+    policy
+    |> Keyword.validate!(@sanitize_policy)
+    |> Map.new()
+    |> Map.update!(:attributes, &Map.new/1)
   end
 end
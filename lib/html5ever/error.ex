@@ -0,0 +1,55 @@
+defmodule Html5ever.Error do
+  @moduledoc """
+  The error returned by the functions of `Html5ever`, as `{:error, error}`.
+
+  The `:message` describes the error, and the `:reason` is a term that
+  can be matched to handle each kind of error:
+
+    * `{:invalid_utf8, valid_up_to, error_len}` - the input is not valid UTF-8.
+      `valid_up_to` is the position of the first invalid byte, and `error_len`
+      is the length of the invalid sequence, or `nil` when the input ends in
+      the middle of a character. In parser sessions, the position is counted
+      from the start of the document, not from the start of the chunk.
+
+    * `{:limit_exceeded, limit, value}` - a limit like `:max_nodes` was
+      exceeded, where `value` is always the one given in the options, not
+      the one that was reached. For `:max_input_bytes`, it is the limit and
+      not the size of the input.
+
+    * `{:invalid_selector, description}` - the CSS selector is invalid
+      or not supported.
+
+    * `{:invalid_xpath, description}` - the XPath expression is invalid
+      or not supported.
+
+    * `{:invalid_node, node}` - the tree given to `Html5ever.serialize/1`
+      has an invalid node, which is described in the string.
+
+    * `{:node_not_found, id}` - there is no node with the ID.
+
+    * `{:duplicated_node, id}` - the node with the ID appears more than once
+      in the tree given to `Html5ever.flat_serialize/2`.
+
+    * `{:not_an_element, id}` - the node with the ID is not an element.
+
+    * `{:invalid_url, url}` - the base URL given to `Html5ever.extract_links/3`
+      is not a valid absolute URL.
+
+    * `:parser_finished` - the parser session was already finished.
+
+    * `:map_entry` - a map could not be built.
+
+  ## Example
+
+      iex> {:error, error} = Html5ever.parse("<p>Hello</p>", max_nodes: 2)
+      iex> error.reason
+      {:limit_exceeded, :max_nodes, 2}
+      iex> Exception.message(error)
+      "limit exceeded: max_nodes is 2"
+
+  """
+
+  defexception [:reason, :message]
+
+  @type t :: %__MODULE__{reason: term(), message: String.t()}
+end
@@ -21,8 +21,34 @@ defmodule Html5ever.Native do
       System.get_env("HTML5EVER_BUILD") in ["1", "true"] or env_config[:build_from_source],
     version: version
 
-  def parse(_binary, _attrs_as_maps), do: err()
-  def flat_parse(_binary, _attrs_as_maps), do: err()
+  def parse(_binary, _opts), do: err()
+  def flat_parse(_binary, _opts), do: err()
+  def parse_fragment(_binary, _context, _opts), do: err()
+  def flat_parse_fragment(_binary, _context, _opts), do: err()
+  def parse_xml(_binary, _opts), do: err()
+  def flat_parse_xml(_binary, _opts), do: err()
+  def serialize(_tree), do: err()
+  def flat_serialize(_nodes, _id), do: err()
+  def select(_binary, _selector, _opts), do: err()
+  def xpath(_binary, _expression, _opts), do: err()
+  def text_content(_binary, _opts), do: err()
+  def extract_links(_binary, _base_url, _opts), do: err()
+  def metadata(_binary, _opts), do: err()
+  def sanitize(_binary, _policy, _opts), do: err()
+  def sanitize_to_tree(_binary, _policy, _opts), do: err()
+  def rewrite(_binary, _rules, _opts), do: err()
+  def tokenize(_binary, _opts), do: err()
+  def parser_new(_opts), do: err()
+  def parser_feed(_resource, _chunk), do: err()
+  def parser_finish(_resource), do: err()
+  def parse_to_resource(_binary, _opts), do: err()
+  def resource_query(_resource, _name), do: err()
+  def resource_select(_resource, _selector), do: err()
+  def resource_xpath(_resource, _expression), do: err()
+  def resource_text(_resource, _id), do: err()
+  def resource_text_content(_resource, _id), do: err()
+  def resource_attributes(_resource, _id), do: err()
+  def resource_serialize(_resource, _id), do: err()
 
   defp err, do: :erlang.nif_error(:nif_not_loaded)
 end
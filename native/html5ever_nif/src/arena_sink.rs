@@ -19,9 +19,13 @@ extern crate html5ever;
 extern crate rustler;
 extern crate typed_arena;
 
+use html5ever::driver::Parser;
 use html5ever::interface::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
-use html5ever::tendril::{StrTendril, TendrilSink};
-use html5ever::{Attribute, QualName, parse_document};
+use html5ever::tendril::fmt::UTF8;
+use html5ever::tendril::stream::Utf8LossyDecoder;
+use html5ever::tendril::{ByteTendril, StrTendril, TendrilSink};
+use html5ever::{Attribute, LocalName, QualName, local_name, ns, parse_document, parse_fragment};
+use xml5ever::driver::XmlParser;
 
 use rustler::{Encoder, Env, Term};
 use std::borrow::Cow;
@@ -29,21 +33,126 @@ use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
 use std::ptr;
 
-use crate::common::{QualNameWrapper, StrTendrilWrapper, atoms};
+use crate::common::{
+    ExpandedNameWrapper, NamespaceWrapper, QualNameWrapper, QuirksModeWrapper, StrTendrilWrapper,
+    atoms,
+};
+use crate::{Html5everExError, Limit, ParseOpts};
 
 /// By using our ArenaSink type, the arena is filled with parsed HTML.
-pub(crate) fn html5ever_parse_slice_into_arena<'a>(bytes: &[u8], arena: Arena<'a>) -> Ref<'a> {
-    let root_id: usize = 0;
-    let sink = ArenaSink {
-        arena,
-        id: Cell::new(root_id),
-        document: arena.alloc(Node::new(NodeData::Document, root_id)),
-        quirks_mode: Cell::new(QuirksMode::NoQuirks),
-    };
-
-    parse_document(sink, Default::default())
-        .from_utf8()
-        .one(bytes)
+///
+/// Returns an error when one of the limits in the options is exceeded.
+pub(crate) fn html5ever_parse_slice_into_arena<'a>(
+    bytes: &[u8],
+    arena: Arena<'a>,
+    opts: &ParseOpts,
+) -> Result<ParsedDocument<'a>, Html5everExError> {
+    parse_chunks(html5ever_parser(arena, opts), bytes, html5ever_sink)?.check_limits()
+}
+
+/// Same as `html5ever_parse_slice_into_arena`, but the bytes are parsed as an
+/// XML document by xml5ever, which fills the arena through the same sink.
+pub(crate) fn xml5ever_parse_slice_into_arena<'a>(
+    bytes: &[u8],
+    arena: Arena<'a>,
+    opts: &ParseOpts,
+) -> Result<ParsedDocument<'a>, Html5everExError> {
+    let parser = xml5ever::driver::parse_document(ArenaSink::new(arena, opts), Default::default());
+
+    parse_chunks(parser, bytes, xml5ever_sink)?.check_limits()
+}
+
+/// Returns a parser that fills the arena with the HTML it is given,
+/// which can be fed in chunks with `TendrilSink::process`.
+pub(crate) fn html5ever_parser<'a>(arena: Arena<'a>, opts: &ParseOpts) -> Parser<ArenaSink<'a>> {
+    parse_document(ArenaSink::new(arena, opts), Default::default())
+}
+
+/// Same as `html5ever_parse_slice_into_arena`, but the bytes are parsed as a fragment
+/// with the `context` element as their parent, following the fragment parsing algorithm.
+///
+/// The returned document node only contains the nodes of the fragment.
+pub(crate) fn html5ever_parse_fragment_slice_into_arena<'a>(
+    bytes: &[u8],
+    context: &str,
+    arena: Arena<'a>,
+    opts: &ParseOpts,
+) -> Result<ParsedDocument<'a>, Html5everExError> {
+    let parser = parse_fragment(
+        ArenaSink::new_fragment(arena, opts),
+        Default::default(),
+        context_element_name(context),
+        Vec::new(),
+        true,
+    );
+    let parsed = parse_chunks(parser, bytes, html5ever_sink)?;
+
+    // The algorithm appends the fragment nodes to an "html" element that is
+    // the only child of the document. We don't want that element in the results.
+    let document = parsed.document;
+    if let Some(html) = document.first_child.get() {
+        html.detach();
+
+        let mut next_child = html.first_child.get();
+        while let Some(child) = next_child {
+            next_child = child.next_sibling.get();
+            document.append(child)
+        }
+    }
+
+    parsed.check_limits()
+}
+
+/// The size of the chunks of input given to the parser. The limits are
+/// checked after each chunk, so the parsing stops soon after one of
+/// them is exceeded, instead of going through the whole input.
+///
+/// The parser cannot be stopped in the middle of a chunk, so the nodes
+/// are still allocated until the end of the chunk where a limit was
+/// exceeded. The memory used is bounded by the limit plus the nodes of
+/// one chunk, which is what keeps the chunks small.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn parse_chunks<'a, P>(
+    parser: P,
+    bytes: &[u8],
+    sink: fn(&P) -> &ArenaSink<'a>,
+) -> Result<ParsedDocument<'a>, Html5everExError>
+where
+    P: TendrilSink<UTF8, Output = ParsedDocument<'a>>,
+{
+    let mut parser = Utf8LossyDecoder::new(parser);
+
+    for chunk in bytes.chunks(CHUNK_SIZE) {
+        parser.process(ByteTendril::from_slice(chunk));
+        sink(&parser.inner_sink).check_limits()?;
+    }
+
+    Ok(parser.finish())
+}
+
+/// Returns an error when one of the limits was exceeded by the nodes
+/// created so far by the parser.
+pub(crate) fn check_parser_limits(parser: &Parser<ArenaSink>) -> Result<(), Html5everExError> {
+    parser.tokenizer.sink.sink.check_limits()
+}
+
+fn html5ever_sink<'p, 'a>(parser: &'p Parser<ArenaSink<'a>>) -> &'p ArenaSink<'a> {
+    &parser.tokenizer.sink.sink
+}
+
+fn xml5ever_sink<'p, 'a>(parser: &'p XmlParser<ArenaSink<'a>>) -> &'p ArenaSink<'a> {
+    &parser.tokenizer.sink.sink
+}
+
+/// The context element is in the HTML namespace, unless it is the root
+/// of an SVG or MathML subtree.
+fn context_element_name(context: &str) -> QualName {
+    match context.to_ascii_lowercase().as_str() {
+        "svg" => QualName::new(None, ns!(svg), local_name!("svg")),
+        "math" => QualName::new(None, ns!(mathml), local_name!("math")),
+        name => QualName::new(None, ns!(html), LocalName::from(name)),
+    }
 }
 
 pub(crate) type Arena<'arena> = &'arena typed_arena::Arena<Node<'arena>>;
@@ -57,23 +166,64 @@ pub(crate) struct ArenaSink<'arena> {
     document: Ref<'arena>,
     id: Cell<usize>,
     quirks_mode: Cell<QuirksMode>,
+    current_line: Cell<u64>,
+    collect_errors: bool,
+    errors: RefCell<Vec<ParseError>>,
+    max_nodes: Option<usize>,
+    max_depth: Option<usize>,
+    // The depth of the nodes that are returned, which is higher than the
+    // depth of the document for fragments, which are parsed into an element.
+    root_depth: usize,
+    max_attributes_per_element: Option<usize>,
+    // The first limit that was exceeded, with its value.
+    limit_exceeded: Cell<Option<(Limit, usize)>>,
+}
+
+/// The output of the parsing, with the document node and the
+/// information that was collected by the sink.
+pub(crate) struct ParsedDocument<'arena> {
+    pub(crate) document: Ref<'arena>,
+    pub(crate) errors: Vec<ParseError>,
+    pub(crate) quirks_mode: QuirksMode,
+    limit_exceeded: Option<(Limit, usize)>,
+}
+
+impl<'arena> ParsedDocument<'arena> {
+    /// Returns an error when one of the limits was exceeded while parsing.
+    pub(crate) fn check_limits(self) -> Result<Self, Html5everExError> {
+        match self.limit_exceeded {
+            Some((limit, value)) => Err(Html5everExError::LimitExceeded(limit, value)),
+            None => Ok(self),
+        }
+    }
+}
+
+/// A parse error reported by the tokenizer or by the tree builder,
+/// with the line being processed when it happened.
+pub(crate) struct ParseError {
+    message: Cow<'static, str>,
+    line: u64,
 }
 
 /// DOM node which contains links to other nodes in the tree.
 pub(crate) struct Node<'arena> {
-    id: usize,
-    parent: Link<'arena>,
-    next_sibling: Link<'arena>,
-    previous_sibling: Link<'arena>,
-    first_child: Link<'arena>,
-    last_child: Link<'arena>,
-    data: NodeData<'arena>,
+    pub(crate) id: usize,
+    pub(crate) line: u64,
+    pub(crate) parent: Link<'arena>,
+    pub(crate) next_sibling: Link<'arena>,
+    pub(crate) previous_sibling: Link<'arena>,
+    pub(crate) first_child: Link<'arena>,
+    pub(crate) last_child: Link<'arena>,
+    pub(crate) data: NodeData<'arena>,
+    // Only kept up to date by the sink when there is a `max_depth`.
+    depth: Cell<usize>,
 }
 
 /// HTML node data which can be an element, a comment, a string, a DOCTYPE, etc...
 #[derive(Clone)]
 pub enum NodeData<'arena> {
     Document,
+    DocumentFragment,
     Doctype {
         name: StrTendril,
         public_id: StrTendril,
@@ -98,19 +248,21 @@ pub enum NodeData<'arena> {
 }
 
 impl<'arena> Node<'arena> {
-    fn new(data: NodeData<'arena>, id: usize) -> Self {
+    pub(crate) fn new(data: NodeData<'arena>, id: usize, line: u64) -> Self {
         Node {
             parent: Cell::new(None),
             previous_sibling: Cell::new(None),
             next_sibling: Cell::new(None),
             first_child: Cell::new(None),
             last_child: Cell::new(None),
+            depth: Cell::new(0),
             id,
+            line,
             data,
         }
     }
 
-    fn detach(&self) {
+    pub(crate) fn detach(&self) {
         let parent = self.parent.take();
         let previous_sibling = self.previous_sibling.take();
         let next_sibling = self.next_sibling.take();
@@ -128,7 +280,7 @@ impl<'arena> Node<'arena> {
         }
     }
 
-    fn append(&'arena self, new_child: &'arena Self) {
+    pub(crate) fn append(&'arena self, new_child: &'arena Self) {
         new_child.detach();
         new_child.parent.set(Some(self));
         if let Some(last_child) = self.last_child.take() {
@@ -142,7 +294,7 @@ impl<'arena> Node<'arena> {
         self.last_child.set(Some(new_child));
     }
 
-    fn insert_before(&'arena self, new_sibling: &'arena Self) {
+    pub(crate) fn insert_before(&'arena self, new_sibling: &'arena Self) {
         new_sibling.detach();
         new_sibling.parent.set(self.parent.get());
         new_sibling.next_sibling.set(Some(self));
@@ -162,11 +314,115 @@ impl<'arena> Node<'arena> {
 }
 
 impl<'arena> ArenaSink<'arena> {
+    fn new(arena: Arena<'arena>, opts: &ParseOpts) -> Self {
+        let root_id: usize = 0;
+        ArenaSink {
+            arena,
+            id: Cell::new(root_id),
+            document: arena.alloc(Node::new(NodeData::Document, root_id, 1)),
+            quirks_mode: Cell::new(QuirksMode::NoQuirks),
+            current_line: Cell::new(1),
+            collect_errors: opts.parse_errors,
+            errors: RefCell::new(Vec::new()),
+            max_nodes: opts.max_nodes,
+            max_depth: opts.max_depth,
+            root_depth: 0,
+            max_attributes_per_element: opts.max_attributes_per_element,
+            limit_exceeded: Cell::new(None),
+        }
+    }
+
+    /// Same as `new`, but the depth is measured from the children of the
+    /// element the fragment is parsed into.
+    fn new_fragment(arena: Arena<'arena>, opts: &ParseOpts) -> Self {
+        ArenaSink {
+            root_depth: 1,
+            ..ArenaSink::new(arena, opts)
+        }
+    }
+
+    /// Records the limit, unless another one was exceeded before.
+    /// The parsing goes on until the limits are checked.
+    fn exceed(&self, limit: Limit, value: usize) {
+        if self.limit_exceeded.get().is_none() {
+            self.limit_exceeded.set(Some((limit, value)));
+        }
+    }
+
+    fn check_limits(&self) -> Result<(), Html5everExError> {
+        match self.limit_exceeded.get() {
+            Some((limit, value)) => Err(Html5everExError::LimitExceeded(limit, value)),
+            None => Ok(()),
+        }
+    }
+
+    fn check_attributes(&self, attrs: &[Attribute]) {
+        if let Some(max) = self.max_attributes_per_element
+            && attrs.len() > max
+        {
+            self.exceed(Limit::MaxAttributesPerElement, max);
+        }
+    }
+
+    /// Sets the depth of the node that was inserted, and of its descendants,
+    /// since the tree builder can move nodes that already have children, like
+    /// in the adoption agency algorithm. The contents of templates have the
+    /// depth of their element.
+    ///
+    /// The depth of the nodes that are not in the tree is measured from the
+    /// root of their subtree, so it is never higher than the one they get when
+    /// they are inserted, and they are checked again then.
+    fn set_depth(&self, node: Ref<'arena>) {
+        let Some(max) = self.max_depth else {
+            return;
+        };
+
+        let parent_depth = node.parent.get().map_or(0, |parent| parent.depth.get());
+        let mut nodes = vec![(node, parent_depth + 1)];
+
+        while let Some((node, depth)) = nodes.pop() {
+            if depth > max + self.root_depth {
+                self.exceed(Limit::MaxDepth, max);
+                return;
+            }
+
+            node.depth.set(depth);
+
+            if let NodeData::Element {
+                template_contents: Some(contents),
+                ..
+            } = &node.data
+            {
+                contents.depth.set(depth);
+                nodes.extend(
+                    get_children(contents)
+                        .into_iter()
+                        .map(|child| (child, depth + 1)),
+                );
+            }
+
+            nodes.extend(
+                get_children(node)
+                    .into_iter()
+                    .map(|child| (child, depth + 1)),
+            );
+        }
+    }
+
     fn new_node(&self, data: NodeData<'arena>) -> Ref<'arena> {
         let current_id = self.id.get();
         let next_id = current_id + 1;
         self.id.set(next_id);
-        self.arena.alloc(Node::new(data, next_id))
+
+        // The document is not counted.
+        if let Some(max) = self.max_nodes
+            && next_id > max
+        {
+            self.exceed(Limit::MaxNodes, max);
+        }
+
+        self.arena
+            .alloc(Node::new(data, next_id, self.current_line.get()))
     }
 
     fn append_common<P, A>(&self, child: NodeOrText<Ref<'arena>>, previous: P, append: A)
@@ -192,7 +448,8 @@ impl<'arena> ArenaSink<'arena> {
             NodeOrText::AppendNode(node) => node,
         };
 
-        append(new_node)
+        append(new_node);
+        self.set_depth(new_node);
     }
 }
 
@@ -203,17 +460,33 @@ impl<'arena> ArenaSink<'arena> {
 /// For deeper understating of each function go to the TreeSink declaration.
 impl<'arena> TreeSink for ArenaSink<'arena> {
     type Handle = Ref<'arena>;
-    type Output = Ref<'arena>;
+    type Output = ParsedDocument<'arena>;
     type ElemName<'a>
         = &'a QualName
     where
         Self: 'a;
 
-    fn finish(self) -> Ref<'arena> {
-        self.document
+    fn finish(self) -> ParsedDocument<'arena> {
+        ParsedDocument {
+            document: self.document,
+            errors: self.errors.into_inner(),
+            quirks_mode: self.quirks_mode.get(),
+            limit_exceeded: self.limit_exceeded.get(),
+        }
     }
 
-    fn parse_error(&self, _: Cow<'static, str>) {}
+    fn parse_error(&self, message: Cow<'static, str>) {
+        if self.collect_errors {
+            self.errors.borrow_mut().push(ParseError {
+                message,
+                line: self.current_line.get(),
+            });
+        }
+    }
+
+    fn set_current_line(&self, line_number: u64) {
+        self.current_line.set(line_number);
+    }
 
     fn get_document(&self) -> Ref<'arena> {
         self.document
@@ -264,11 +537,13 @@ impl<'arena> TreeSink for ArenaSink<'arena> {
         attrs: Vec<Attribute>,
         flags: ElementFlags,
     ) -> Ref<'arena> {
+        self.check_attributes(&attrs);
+
         self.new_node(NodeData::Element {
             name,
             attrs: RefCell::new(attrs),
             template_contents: if flags.template {
-                Some(self.new_node(NodeData::Document))
+                Some(self.new_node(NodeData::DocumentFragment))
             } else {
                 None
             },
@@ -322,11 +597,14 @@ impl<'arena> TreeSink for ArenaSink<'arena> {
         public_id: StrTendril,
         system_id: StrTendril,
     ) {
-        self.document.append(self.new_node(NodeData::Doctype {
+        let doctype = self.new_node(NodeData::Doctype {
             name,
             public_id,
             system_id,
-        }))
+        });
+
+        self.document.append(doctype);
+        self.set_depth(doctype);
     }
 
     fn add_attrs_if_missing(&self, target: &Ref<'arena>, attrs: Vec<Attribute>) {
@@ -345,10 +623,13 @@ impl<'arena> TreeSink for ArenaSink<'arena> {
                 .into_iter()
                 .filter(|attr| !existing_names.contains(&attr.name)),
         );
+
+        self.check_attributes(&existing);
     }
 
     fn remove_from_parent(&self, target: &Ref<'arena>) {
-        target.detach()
+        target.detach();
+        self.set_depth(target);
     }
 
     fn reparent_children(&self, node: &Ref<'arena>, new_parent: &Ref<'arena>) {
@@ -356,95 +637,187 @@ impl<'arena> TreeSink for ArenaSink<'arena> {
         while let Some(child) = next_child {
             debug_assert!(ptr::eq::<Node>(child.parent.get().unwrap(), *node));
             next_child = child.next_sibling.get();
-            new_parent.append(child)
+            new_parent.append(child);
+            self.set_depth(child);
         }
     }
 }
 
+/// Encodes the node in the nested format.
+///
+/// The tree is walked with an explicit stack instead of recursion, so
+/// deeply nested documents do not overflow the stack of the scheduler.
+/// Each node is encoded after its children, which are kept in `terms`
+/// until their parent is closed.
 pub(crate) fn nodes_to_term<'arena, 'env>(
     env: Env<'env>,
     node: &Node<'arena>,
-    attrs_as_maps: bool,
+    opts: &ParseOpts,
 ) -> Term<'env> {
-    match &node.data {
-        NodeData::Document => {
-            let mut terms: Vec<Term> = Vec::new();
-            let mut child = node.first_child.get();
-            while let Some(current_child) = child {
-                let encoded_child = nodes_to_term(env, current_child, attrs_as_maps);
-                terms.push(encoded_child);
-                child = current_child.next_sibling.get();
-            }
+    enum Op<'a, 'arena> {
+        Open(&'a Node<'arena>),
+        // The node and the index of its first child in `terms`.
+        Close(&'a Node<'arena>, usize),
+    }
 
-            terms.encode(env)
+    let mut terms: Vec<Term> = Vec::new();
+    let mut ops = vec![Op::Open(node)];
+
+    while let Some(op) = ops.pop() {
+        match op {
+            Op::Open(node) => {
+                ops.push(Op::Close(node, terms.len()));
+
+                let mut children = get_children(node);
+
+                // The contents of templates are encoded as their children.
+                if let NodeData::Element {
+                    template_contents: Some(contents),
+                    ..
+                } = &node.data
+                    && opts.template_contents
+                {
+                    children.extend(get_children(contents));
+                }
+
+                ops.extend(children.into_iter().rev().map(Op::Open));
+            }
+            Op::Close(node, first_child) => {
+                let children = terms.split_off(first_child);
+                terms.push(node_to_term(env, node, children, opts));
+            }
         }
+    }
+
+    terms.pop().expect("the root node is encoded")
+}
+
+/// Encodes a single node, given the terms of its children.
+fn node_to_term<'env>(
+    env: Env<'env>,
+    node: &Node,
+    children: Vec<Term<'env>>,
+    opts: &ParseOpts,
+) -> Term<'env> {
+    match &node.data {
+        NodeData::Document | NodeData::DocumentFragment => children.encode(env),
         NodeData::Doctype {
             name,
             public_id,
             system_id,
-        } => (
-            atoms::doctype(),
-            StrTendrilWrapper(name),
-            StrTendrilWrapper(public_id),
-            StrTendrilWrapper(system_id),
-        )
-            .encode(env),
+        } => {
+            let (name, public_id, system_id) = (
+                StrTendrilWrapper(name),
+                StrTendrilWrapper(public_id),
+                StrTendrilWrapper(system_id),
+            );
+
+            if opts.source_positions {
+                let meta = node_meta_to_term(env, node);
+                (atoms::doctype(), name, public_id, system_id, meta).encode(env)
+            } else {
+                (atoms::doctype(), name, public_id, system_id).encode(env)
+            }
+        }
         NodeData::Text { contents } => {
             let text = contents.borrow();
             StrTendrilWrapper(&text).encode(env)
         }
         NodeData::Comment { contents } => {
-            (atoms::comment(), StrTendrilWrapper(contents)).encode(env)
+            let contents = StrTendrilWrapper(contents);
+
+            if opts.source_positions {
+                let meta = node_meta_to_term(env, node);
+                (atoms::comment(), contents, meta).encode(env)
+            } else {
+                (atoms::comment(), contents).encode(env)
+            }
         }
         NodeData::Element { name, attrs, .. } => {
-            let mut terms: Vec<Term> = Vec::new();
+            let name = if opts.namespaces {
+                ExpandedNameWrapper(name).encode(env)
+            } else {
+                QualNameWrapper(name).encode(env)
+            };
+            let attrs = attributes_to_term(env, &attrs.borrow(), opts);
 
-            let mut child = node.first_child.get();
-            while let Some(current_child) = child {
-                let encoded_child = nodes_to_term(env, current_child, attrs_as_maps);
-                terms.push(encoded_child);
-                child = current_child.next_sibling.get();
+            if opts.source_positions {
+                let meta = node_meta_to_term(env, node);
+                (name, attrs, children, meta).encode(env)
+            } else {
+                (name, attrs, children).encode(env)
             }
+        }
+        NodeData::ProcessingInstruction { target, contents } => {
+            let (target, contents) = (StrTendrilWrapper(target), StrTendrilWrapper(contents));
 
-            (
-                &name.local.to_string(),
-                attributes_to_term(env, attrs, attrs_as_maps),
-                terms,
-            )
-                .encode(env)
+            if opts.source_positions {
+                let meta = node_meta_to_term(env, node);
+                (atoms::process_instruction(), target, contents, meta).encode(env)
+            } else {
+                (atoms::process_instruction(), target, contents).encode(env)
+            }
         }
-        NodeData::ProcessingInstruction { target, contents } => (
-            atoms::process_instruction(),
-            StrTendrilWrapper(target),
-            StrTendrilWrapper(contents),
-        )
-            .encode(env),
     }
 }
 
-fn attributes_to_term<'a>(
+/// The metadata of a node in the nested format, which is a map
+/// with the line where the node was created by the parser.
+fn node_meta_to_term<'env>(env: Env<'env>, node: &Node) -> Term<'env> {
+    let pairs = [(atoms::line().encode(env), node.line.encode(env))];
+    Term::map_from_pairs(env, &pairs).unwrap()
+}
+
+pub(crate) fn attributes_to_term<'a>(
     env: Env<'a>,
-    attributes: &RefCell<Vec<Attribute>>,
-    as_maps: bool,
+    attributes: &[Attribute],
+    opts: &ParseOpts,
 ) -> Term<'a> {
-    let attrs = attributes.borrow();
-    let pairs: Vec<(QualNameWrapper, StrTendrilWrapper)> = attrs
+    let pairs: Vec<(Term, StrTendrilWrapper)> = attributes
         .iter()
-        .map(|a| (QualNameWrapper(&a.name), StrTendrilWrapper(&a.value)))
+        .map(|a| {
+            // Most attributes have no namespace, so the name is kept as a string for them.
+            let name = if opts.namespaces && a.name.ns != ns!() {
+                ExpandedNameWrapper(&a.name).encode(env)
+            } else {
+                QualNameWrapper(&a.name).encode(env)
+            };
+            (name, StrTendrilWrapper(&a.value))
+        })
         .collect();
 
-    if as_maps {
+    if opts.attributes_as_maps {
         Term::map_from_pairs(env, &pairs).unwrap()
     } else {
         pairs.encode(env)
     }
 }
 
+pub(crate) fn parse_errors_to_term<'env>(
+    env: Env<'env>,
+    errors: &[ParseError],
+) -> Result<Term<'env>, crate::Html5everExError> {
+    let atom_line = atoms::line().encode(env);
+    let atom_message = atoms::message().encode(env);
+
+    let mut terms: Vec<Term> = Vec::with_capacity(errors.len());
+    for error in errors {
+        let message: &str = &error.message;
+        let pairs: Vec<(Term, Term)> = vec![
+            (atom_line, error.line.encode(env)),
+            (atom_message, message.encode(env)),
+        ];
+        terms.push(Term::map_from_pairs(env, &pairs).map_err(rustler_error_to_map_entry_error)?);
+    }
+
+    Ok(terms.encode(env))
+}
+
 fn rustler_error_to_map_entry_error(_err: rustler::error::Error) -> crate::Html5everExError {
     crate::Html5everExError::MapEntry
 }
 
-fn get_children<'a>(node: &Node<'a>) -> Vec<Ref<'a>> {
+pub(crate) fn get_children<'a>(node: &Node<'a>) -> Vec<Ref<'a>> {
     let mut children: Vec<&Node> = Vec::new();
     let mut child = node.first_child.get();
     while let Some(current_child) = child {
@@ -458,7 +831,8 @@ fn get_children<'a>(node: &Node<'a>) -> Vec<Ref<'a>> {
 pub(crate) fn nodes_to_flat_term<'env>(
     env: Env<'env>,
     root_node: &Node,
-    attrs_as_maps: bool,
+    quirks_mode: QuirksMode,
+    opts: &ParseOpts,
 ) -> Result<Term<'env>, crate::Html5everExError> {
     let mut main_map = ::rustler::types::map::map_new(env);
     let mut nodes_map = ::rustler::types::map::map_new(env);
@@ -468,7 +842,9 @@ pub(crate) fn nodes_to_flat_term<'env>(
     let atom_contents = atoms::contents().encode(env);
     let atom_element = atoms::element().encode(env);
     let atom_id = atoms::id().encode(env);
+    let atom_line = atoms::line().encode(env);
     let atom_name = atoms::name().encode(env);
+    let atom_namespace = atoms::namespace().encode(env);
     let atom_parent = atoms::parent().encode(env);
     let atom_text = atoms::text().encode(env);
     let atom_type = atoms::type_().encode(env);
@@ -486,6 +862,10 @@ pub(crate) fn nodes_to_flat_term<'env>(
                     (atom_children, children_ids.encode(env)),
                     (atom_id, node_id_encoded),
                     (atom_parent, node.parent.get().map(|n| n.id).encode(env)),
+                    (
+                        atoms::quirks_mode().encode(env),
+                        QuirksModeWrapper(quirks_mode).encode(env),
+                    ),
                     (atom_type, atoms::document().encode(env)),
                 ];
                 let document_map =
@@ -505,13 +885,50 @@ pub(crate) fn nodes_to_flat_term<'env>(
 
                 main_map
             }
-            NodeData::Doctype { name, .. } => {
+            NodeData::DocumentFragment => {
+                let mut children = get_children(node);
+                let children_ids: Vec<usize> = children.iter().map(|c| c.id).collect();
                 let pairs: Vec<(Term, Term)> = vec![
+                    (atom_children, children_ids.encode(env)),
+                    (atom_id, node_id_encoded),
+                    (atom_parent, node.parent.get().map(|n| n.id).encode(env)),
+                    (atom_type, atoms::document_fragment().encode(env)),
+                ];
+                let fragment_map =
+                    Term::map_from_pairs(env, &pairs).map_err(rustler_error_to_map_entry_error)?;
+
+                nodes_map = nodes_map
+                    .map_put(node_id_encoded, fragment_map)
+                    .map_err(rustler_error_to_map_entry_error)?;
+
+                for child in children.iter_mut() {
+                    nodes.push(child)
+                }
+
+                nodes_map
+            }
+            NodeData::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => {
+                let mut pairs: Vec<(Term, Term)> = vec![
                     (atom_id, node_id_encoded),
                     (atom_parent, node.parent.get().map(|n| n.id).encode(env)),
                     (atom_type, atoms::doctype().encode(env)),
                     (atom_name, StrTendrilWrapper(name).encode(env)),
+                    (
+                        atoms::public_id().encode(env),
+                        StrTendrilWrapper(public_id).encode(env),
+                    ),
+                    (
+                        atoms::system_id().encode(env),
+                        StrTendrilWrapper(system_id).encode(env),
+                    ),
                 ];
+                if opts.source_positions {
+                    pairs.push((atom_line, node.line.encode(env)));
+                }
                 let doctype_map =
                     Term::map_from_pairs(env, &pairs).map_err(rustler_error_to_map_entry_error)?;
 
@@ -524,12 +941,15 @@ pub(crate) fn nodes_to_flat_term<'env>(
             NodeData::Text { contents } => {
                 let text = contents.borrow();
 
-                let pairs: Vec<(Term, Term)> = vec![
+                let mut pairs: Vec<(Term, Term)> = vec![
                     (atom_id, node_id_encoded),
                     (atom_parent, node.parent.get().map(|n| n.id).encode(env)),
                     (atom_type, atom_text),
                     (atom_contents, StrTendrilWrapper(&text).encode(env)),
                 ];
+                if opts.source_positions {
+                    pairs.push((atom_line, node.line.encode(env)));
+                }
                 let text_map =
                     Term::map_from_pairs(env, &pairs).map_err(rustler_error_to_map_entry_error)?;
 
@@ -540,12 +960,15 @@ pub(crate) fn nodes_to_flat_term<'env>(
                 nodes_map
             }
             NodeData::Comment { contents } => {
-                let pairs: Vec<(Term, Term)> = vec![
+                let mut pairs: Vec<(Term, Term)> = vec![
                     (atom_id, node_id_encoded),
                     (atom_parent, node.parent.get().map(|n| n.id).encode(env)),
                     (atom_type, atoms::comment().encode(env)),
                     (atom_contents, StrTendrilWrapper(contents).encode(env)),
                 ];
+                if opts.source_positions {
+                    pairs.push((atom_line, node.line.encode(env)));
+                }
                 let comment_map =
                     Term::map_from_pairs(env, &pairs).map_err(rustler_error_to_map_entry_error)?;
 
@@ -555,17 +978,32 @@ pub(crate) fn nodes_to_flat_term<'env>(
 
                 nodes_map
             }
-            NodeData::Element { name, attrs, .. } => {
+            NodeData::Element {
+                name,
+                attrs,
+                template_contents,
+                ..
+            } => {
                 let mut children = get_children(node);
                 let children_ids: Vec<usize> = children.iter().map(|c| c.id).collect();
-                let pairs: Vec<(Term, Term)> = vec![
-                    (atom_attrs, attributes_to_term(env, attrs, attrs_as_maps)),
+                let mut pairs: Vec<(Term, Term)> = vec![
+                    (atom_attrs, attributes_to_term(env, &attrs.borrow(), opts)),
                     (atom_children, children_ids.encode(env)),
                     (atom_id, node_id_encoded),
                     (atom_name, name.local.encode(env)),
                     (atom_parent, node.parent.get().map(|n| n.id).encode(env)),
                     (atom_type, atom_element),
                 ];
+                if opts.source_positions {
+                    pairs.push((atom_line, node.line.encode(env)));
+                }
+                if opts.namespaces {
+                    pairs.push((atom_namespace, NamespaceWrapper(&name.ns).encode(env)));
+                }
+                if let Some(contents) = template_contents.filter(|_| opts.template_contents) {
+                    pairs.push((atoms::content().encode(env), contents.id.encode(env)));
+                    nodes.push(contents);
+                }
                 let element_map =
                     Term::map_from_pairs(env, &pairs).map_err(rustler_error_to_map_entry_error)?;
 
@@ -580,13 +1018,16 @@ pub(crate) fn nodes_to_flat_term<'env>(
                 nodes_map
             }
             NodeData::ProcessingInstruction { target, contents } => {
-                let pairs: Vec<(Term, Term)> = vec![
+                let mut pairs: Vec<(Term, Term)> = vec![
                     (atom_id, node_id_encoded),
                     (atom_parent, node.parent.get().map(|n| n.id).encode(env)),
                     (atom_type, atoms::process_instruction().encode(env)),
                     (atom_name, StrTendrilWrapper(target).encode(env)),
                     (atom_contents, StrTendrilWrapper(contents).encode(env)),
                 ];
+                if opts.source_positions {
+                    pairs.push((atom_line, node.line.encode(env)));
+                }
                 let process_instruction_map =
                     Term::map_from_pairs(env, &pairs).map_err(rustler_error_to_map_entry_error)?;
 
@@ -1,6 +1,7 @@
 use rustler::{Encoder, Env, Term};
 
-use html5ever::QualName;
+use html5ever::interface::QuirksMode;
+use html5ever::{Namespace, QualName, ns};
 use tendril::StrTendril;
 
 // Zero-cost wrapper types which makes it possible to implement
@@ -9,6 +10,10 @@ use tendril::StrTendril;
 // that produced the cleanest and least noisy code.
 pub struct QualNameWrapper<'a>(pub &'a QualName);
 pub struct StrTendrilWrapper<'a>(pub &'a StrTendril);
+pub struct QuirksModeWrapper(pub QuirksMode);
+pub struct NamespaceWrapper<'a>(pub &'a Namespace);
+// Encodes the name as `{namespace, local_name}`.
+pub struct ExpandedNameWrapper<'a>(pub &'a QualName);
 
 impl Encoder for QualNameWrapper<'_> {
     fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
@@ -22,26 +27,121 @@ impl Encoder for StrTendrilWrapper<'_> {
         data.encode(env)
     }
 }
+impl Encoder for QuirksModeWrapper {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self.0 {
+            QuirksMode::NoQuirks => atoms::no_quirks().encode(env),
+            QuirksMode::LimitedQuirks => atoms::limited_quirks().encode(env),
+            QuirksMode::Quirks => atoms::quirks().encode(env),
+        }
+    }
+}
+
+impl Encoder for NamespaceWrapper<'_> {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let namespace = self.0;
+
+        if *namespace == ns!(html) {
+            atoms::html().encode(env)
+        } else if *namespace == ns!(svg) {
+            atoms::svg().encode(env)
+        } else if *namespace == ns!(mathml) {
+            atoms::mathml().encode(env)
+        } else if *namespace == ns!(xlink) {
+            atoms::xlink().encode(env)
+        } else if *namespace == ns!(xml) {
+            atoms::xml().encode(env)
+        } else if *namespace == ns!(xmlns) {
+            atoms::xmlns().encode(env)
+        } else if *namespace == ns!() {
+            atoms::nil().encode(env)
+        } else {
+            let data: &str = namespace;
+            data.encode(env)
+        }
+    }
+}
+impl Encoder for ExpandedNameWrapper<'_> {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let local: &str = &self.0.local;
+        (NamespaceWrapper(&self.0.ns), local).encode(env)
+    }
+}
 
 pub(crate) mod atoms {
     rustler::atoms! {
         nil,
+        ok,
 
         type_ = "type",
         document,
+        document_fragment,
         element,
         text,
         doctype,
         comment,
         process_instruction = "pi",
 
+        start_tag,
+        end_tag,
+        characters,
+        eof,
+        parse_error,
+
         name,
+        public_id,
+        system_id,
         nodes,
         root,
         id,
         parent,
         children,
         contents,
+        content,
         attrs,
+        encoding,
+        errors,
+        line,
+        message,
+        attribute,
+        url,
+        title,
+        lang,
+        charset,
+        canonical,
+        meta,
+        json_ld,
+
+        namespace,
+        html,
+        svg,
+        mathml,
+        xlink,
+        xml,
+        xmlns,
+
+        quirks_mode,
+        no_quirks,
+        limited_quirks,
+        quirks,
+
+        nan,
+        infinity,
+        neg_infinity,
+
+        error_module = "Elixir.Html5ever.Error",
+        exception = "__exception__",
+        reason,
+        invalid_utf8,
+        map_entry,
+        invalid_node,
+        node_not_found,
+        duplicated_node,
+        not_an_element,
+        invalid_selector,
+        invalid_xpath,
+        parser_finished,
+        invalid_url,
+        limit_exceeded,
     }
 }
@@ -0,0 +1,304 @@
+// Detection of the character encoding of HTML documents.
+//
+// This follows the encoding sniffing algorithm from the HTML spec:
+// https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm
+//
+// Instead of using the locale of the user as the last resort, we check if
+// the document is valid UTF-8, and use "windows-1252" if it is not.
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252, X_USER_DEFINED};
+
+/// The number of bytes that the prescan is going to look at.
+pub(crate) const PRESCAN_LIMIT: usize = 1024;
+
+/// Returns the encoding of the document and the length of its BOM.
+///
+/// The order of precedence is: the byte order mark, the encoding
+/// from the transport layer (like the `Content-Type` header), and
+/// the `<meta>` tags found in the beginning of the document.
+pub(crate) fn sniff(bytes: &[u8], transport_label: Option<&str>) -> (&'static Encoding, usize) {
+    sniff_bytes(bytes, transport_label, true)
+}
+
+/// Same as `sniff`, but for the first bytes of a document that did not
+/// arrive entirely yet. A character cut at the end of the bytes does
+/// not make them invalid UTF-8.
+pub(crate) fn sniff_prefix(
+    bytes: &[u8],
+    transport_label: Option<&str>,
+) -> (&'static Encoding, usize) {
+    sniff_bytes(bytes, transport_label, false)
+}
+
+fn sniff_bytes(
+    bytes: &[u8],
+    transport_label: Option<&str>,
+    complete: bool,
+) -> (&'static Encoding, usize) {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return (encoding, bom_length);
+    }
+
+    if let Some(encoding) = transport_label.and_then(|label| Encoding::for_label(label.as_bytes()))
+    {
+        return (encoding, 0);
+    }
+
+    if let Some(encoding) = prescan(&bytes[..bytes.len().min(PRESCAN_LIMIT)]) {
+        return (encoding, 0);
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(_) => (UTF_8, 0),
+        Err(error) if !complete && error.error_len().is_none() => (UTF_8, 0),
+        Err(_) => (WINDOWS_1252, 0),
+    }
+}
+
+/// Prescans the bytes looking for a `<meta>` tag declaring the encoding.
+///
+/// See https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+
+        if rest.starts_with(b"<!--") {
+            // The dashes of "<!--" can be part of the "-->" as well.
+            position += 2 + find(&rest[2..], b"-->")? + 2;
+        } else if starts_with_ignore_ascii_case(rest, b"<meta")
+            && rest
+                .get(5)
+                .is_some_and(|&byte| is_space(byte) || byte == b'/')
+        {
+            position += 6;
+
+            if let Some(encoding) = meta_encoding(bytes, &mut position) {
+                return Some(encoding);
+            }
+
+            if position >= bytes.len() {
+                return None;
+            }
+        } else if rest.len() > 2
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic() || (rest[1] == b'/' && rest[2].is_ascii_alphabetic()))
+        {
+            // Skips the tag and its attributes.
+            position += rest
+                .iter()
+                .position(|&byte| is_space(byte) || byte == b'>')?;
+
+            while get_attribute(bytes, &mut position).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            position += rest.iter().position(|&byte| byte == b'>')?;
+        }
+
+        position += 1;
+    }
+
+    None
+}
+
+/// Reads the attributes of a `<meta>` tag and returns the declared encoding, if any.
+fn meta_encoding(bytes: &[u8], position: &mut usize) -> Option<&'static Encoding> {
+    let mut attribute_names: Vec<Vec<u8>> = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma: Option<bool> = None;
+    let mut charset: Option<&'static Encoding> = None;
+
+    while let Some((name, value)) = get_attribute(bytes, position) {
+        if attribute_names.contains(&name) {
+            continue;
+        }
+
+        match name.as_slice() {
+            b"http-equiv" if value == b"content-type" => got_pragma = true,
+            // The charset from the "content" is only used if there is
+            // no valid "charset" attribute before it.
+            b"content" if charset.is_none() => {
+                if let Some(encoding) = extract_encoding_from_content(&value) {
+                    charset = Some(encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            b"charset" => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+
+        attribute_names.push(name);
+    }
+
+    // The tag was cut by the end of the prescanned bytes.
+    if *position >= bytes.len() {
+        return None;
+    }
+
+    match need_pragma {
+        Some(true) if !got_pragma => None,
+        Some(_) => charset.map(|encoding| {
+            if encoding == UTF_16BE || encoding == UTF_16LE {
+                UTF_8
+            } else if encoding == X_USER_DEFINED {
+                WINDOWS_1252
+            } else {
+                encoding
+            }
+        }),
+        None => None,
+    }
+}
+
+/// Reads the next attribute of a tag, with its name and value in lowercase.
+///
+/// See https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+fn get_attribute(bytes: &[u8], position: &mut usize) -> Option<(Vec<u8>, Vec<u8>)> {
+    while is_space(*bytes.get(*position)?) || bytes[*position] == b'/' {
+        *position += 1;
+    }
+
+    if bytes[*position] == b'>' {
+        return None;
+    }
+
+    let mut name = Vec::new();
+
+    // The name of the attribute.
+    loop {
+        let byte = *bytes.get(*position)?;
+
+        match byte {
+            b'=' if !name.is_empty() => {
+                *position += 1;
+                return get_attribute_value(bytes, position, name);
+            }
+            b'/' | b'>' => return Some((name, Vec::new())),
+            _ if is_space(byte) => break,
+            _ => name.push(byte.to_ascii_lowercase()),
+        }
+
+        *position += 1;
+    }
+
+    // Spaces between the name and the "=" sign.
+    while is_space(*bytes.get(*position)?) {
+        *position += 1;
+    }
+
+    if bytes[*position] != b'=' {
+        return Some((name, Vec::new()));
+    }
+
+    *position += 1;
+    get_attribute_value(bytes, position, name)
+}
+
+fn get_attribute_value(
+    bytes: &[u8],
+    position: &mut usize,
+    name: Vec<u8>,
+) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut value = Vec::new();
+
+    while is_space(*bytes.get(*position)?) {
+        *position += 1;
+    }
+
+    match bytes[*position] {
+        quote @ (b'"' | b'\'') => loop {
+            *position += 1;
+            let byte = *bytes.get(*position)?;
+
+            if byte == quote {
+                *position += 1;
+                return Some((name, value));
+            }
+
+            value.push(byte.to_ascii_lowercase());
+        },
+        b'>' => return Some((name, value)),
+        byte => {
+            value.push(byte.to_ascii_lowercase());
+            *position += 1;
+        }
+    }
+
+    loop {
+        let byte = *bytes.get(*position)?;
+
+        if is_space(byte) || byte == b'>' {
+            return Some((name, value));
+        }
+
+        value.push(byte.to_ascii_lowercase());
+        *position += 1;
+    }
+}
+
+/// Extracts the encoding from the value of the "content" attribute,
+/// like in `<meta http-equiv="Content-Type" content="text/html; charset=utf-8">`.
+///
+/// See https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+pub(crate) fn extract_encoding_from_content(content: &[u8]) -> Option<&'static Encoding> {
+    let mut position = 0;
+
+    loop {
+        position += find_ignore_ascii_case(&content[position..], b"charset")? + b"charset".len();
+
+        position += content[position..]
+            .iter()
+            .take_while(|&&byte| is_space(byte))
+            .count();
+
+        if content.get(position) == Some(&b'=') {
+            break;
+        }
+    }
+
+    position += 1;
+    position += content[position..]
+        .iter()
+        .take_while(|&&byte| is_space(byte))
+        .count();
+
+    let label = match *content.get(position)? {
+        quote @ (b'"' | b'\'') => {
+            let rest = &content[position + 1..];
+            &rest[..rest.iter().position(|&byte| byte == quote)?]
+        }
+        _ => {
+            let rest = &content[position..];
+            let length = rest
+                .iter()
+                .position(|&byte| is_space(byte) || byte == b';')
+                .unwrap_or(rest.len());
+            &rest[..length]
+        }
+    };
+
+    Encoding::for_label(label)
+}
+
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn find_ignore_ascii_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+fn starts_with_ignore_ascii_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
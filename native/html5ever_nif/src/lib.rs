@@ -1,60 +1,709 @@
 mod arena_sink;
 mod common;
+mod encoding;
+mod links;
+mod metadata;
+mod resource;
+mod rewriter;
+mod sanitizer;
+mod selector;
+mod serializer;
+mod session;
+mod text;
+mod token_sink;
+mod xpath;
 
+use std::borrow::Cow;
+
+use encoding_rs::Encoding;
 use rustler::types::binary::Binary;
-use rustler::{Env, Term};
+use rustler::{Atom, Encoder, Env, NifMap, NifResult, ResourceArc, Term};
 
 use thiserror::Error;
 
+use crate::arena_sink::NodeData;
+use crate::common::{QuirksModeWrapper, atoms};
+use crate::resource::DocumentResource;
+use crate::rewriter::{Action, Rule};
+use crate::sanitizer::SanitizePolicy;
+use crate::selector::SelectorList;
+use crate::session::ParserResource;
+use crate::xpath::XPath;
+
 #[derive(Error, Debug)]
 pub enum Html5everExError {
-    #[error("cannot transform bytes from binary to a valid UTF8 string")]
-    BytesToUtf8(#[from] std::str::Utf8Error),
+    #[error("cannot transform bytes from binary to a valid UTF8 string at position {valid_up_to}")]
+    InvalidUtf8 {
+        /// The position of the first invalid byte.
+        valid_up_to: usize,
+        /// The length of the invalid sequence, which is `None`
+        /// when the input ends in the middle of a character.
+        error_len: Option<usize>,
+    },
 
     #[error("cannot insert entry in a map")]
     MapEntry,
+
+    #[error("invalid node: {0}")]
+    InvalidNode(String),
+
+    #[error("cannot find node with ID {0}")]
+    NodeNotFound(usize),
+
+    #[error("node with ID {0} appears more than once in the tree")]
+    DuplicatedNode(usize),
+
+    #[error("node with ID {0} is not an element")]
+    NotAnElement(usize),
+
+    #[error("invalid selector: {0}")]
+    InvalidSelector(String),
+
+    #[error("invalid XPath expression: {0}")]
+    InvalidXPath(String),
+
+    #[error("the parser is already finished")]
+    ParserFinished,
+
+    #[error("invalid URL: {0}")]
+    InvalidUrl(String),
+
+    /// The limit that was exceeded, with the value given in the options.
+    #[error("limit exceeded: {0} is {1}")]
+    LimitExceeded(Limit, usize),
+}
+
+/// The limits that can be set in the options, named after them.
+#[derive(Clone, Copy, Debug)]
+pub enum Limit {
+    MaxNodes,
+    MaxDepth,
+    MaxAttributesPerElement,
+    MaxInputBytes,
+}
+
+impl Limit {
+    fn name(self) -> &'static str {
+        match self {
+            Limit::MaxNodes => "max_nodes",
+            Limit::MaxDepth => "max_depth",
+            Limit::MaxAttributesPerElement => "max_attributes_per_element",
+            Limit::MaxInputBytes => "max_input_bytes",
+        }
+    }
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl rustler::Encoder for Limit {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        Atom::from_str(env, self.name())
+            .expect("the name is a valid atom")
+            .encode(env)
+    }
+}
+
+impl From<std::str::Utf8Error> for Html5everExError {
+    fn from(error: std::str::Utf8Error) -> Self {
+        Html5everExError::InvalidUtf8 {
+            valid_up_to: error.valid_up_to(),
+            error_len: error.error_len(),
+        }
+    }
+}
+
+impl Html5everExError {
+    /// The reason of the error, as a term that can be matched in Elixir,
+    /// like `{:limit_exceeded, :max_nodes, 1000}`.
+    fn reason<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            Html5everExError::InvalidUtf8 {
+                valid_up_to,
+                error_len,
+            } => (atoms::invalid_utf8(), valid_up_to, error_len).encode(env),
+            Html5everExError::MapEntry => atoms::map_entry().encode(env),
+            Html5everExError::InvalidNode(node) => (atoms::invalid_node(), node).encode(env),
+            Html5everExError::NodeNotFound(id) => (atoms::node_not_found(), id).encode(env),
+            Html5everExError::DuplicatedNode(id) => (atoms::duplicated_node(), id).encode(env),
+            Html5everExError::NotAnElement(id) => (atoms::not_an_element(), id).encode(env),
+            Html5everExError::InvalidSelector(description) => {
+                (atoms::invalid_selector(), description).encode(env)
+            }
+            Html5everExError::InvalidXPath(description) => {
+                (atoms::invalid_xpath(), description).encode(env)
+            }
+            Html5everExError::ParserFinished => atoms::parser_finished().encode(env),
+            Html5everExError::InvalidUrl(url) => (atoms::invalid_url(), url).encode(env),
+            Html5everExError::LimitExceeded(limit, value) => {
+                (atoms::limit_exceeded(), limit, value).encode(env)
+            }
+        }
+    }
 }
 
+// Encodes the errors as `Html5ever.Error` exceptions, with the reason
+// and the message.
 impl rustler::Encoder for Html5everExError {
     fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
-        format!("{self}").encode(env)
+        let pairs = [
+            (
+                rustler::types::atom::__struct__().encode(env),
+                atoms::error_module().encode(env),
+            ),
+            (atoms::exception().encode(env), true.encode(env)),
+            (atoms::reason().encode(env), self.reason(env)),
+            (atoms::message().encode(env), self.to_string().encode(env)),
+        ];
+
+        Term::map_from_pairs(env, &pairs).expect("the keys are unique")
     }
 }
 
-#[rustler::nif(schedule = "DirtyCpu")]
-fn parse<'a>(
-    env: Env<'a>,
-    binary: Binary,
+// Makes the errors return `{:error, reason}` from NIFs returning a `NifResult`.
+impl From<Html5everExError> for rustler::Error {
+    fn from(err: Html5everExError) -> Self {
+        rustler::Error::Term(Box::new(err))
+    }
+}
+
+/// The options of the parse functions.
+/// All the keys are filled with defaults in the Elixir side.
+#[derive(NifMap)]
+pub(crate) struct ParseOpts {
     attributes_as_maps: bool,
-) -> Result<Term<'a>, Html5everExError> {
-    let utf8 = std::str::from_utf8(binary.as_slice())?;
+    parse_errors: bool,
+    quirks_mode: bool,
+    source_positions: bool,
+    detect_encoding: bool,
+    transport_encoding: Option<String>,
+    lossy_utf8: bool,
+    namespaces: bool,
+    template_contents: bool,
+    max_nodes: Option<usize>,
+    max_depth: Option<usize>,
+    max_attributes_per_element: Option<usize>,
+    max_input_bytes: Option<usize>,
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse<'a>(env: Env<'a>, binary: Binary, opts: ParseOpts) -> NifResult<Term<'a>> {
+    let (html, encoding) = decode_input(binary.as_slice(), &opts)?;
 
     // Average node size based on web archive is 600:
     // https://discuss.httparchive.org/t/are-there-any-stats-about-dom-sizes-in-modern-pages/1619
     // So using a bit more than that.
     let arena = typed_arena::Arena::with_capacity(800);
 
-    let first_node = arena_sink::html5ever_parse_slice_into_arena(utf8.as_bytes(), &arena);
-    let term = arena_sink::nodes_to_term(env, first_node, attributes_as_maps);
+    let parsed = arena_sink::html5ever_parse_slice_into_arena(html.as_bytes(), &arena, &opts)?;
+    let term = arena_sink::nodes_to_term(env, parsed.document, &opts);
 
-    Ok(term)
+    Ok(nested_result(env, term, &parsed, encoding, &opts)?)
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
-fn flat_parse<'a>(
-    env: Env<'a>,
-    binary: Binary,
-    attributes_as_maps: bool,
-) -> Result<Term<'a>, Html5everExError> {
-    let utf8 = std::str::from_utf8(binary.as_slice())?;
+fn flat_parse<'a>(env: Env<'a>, binary: Binary, opts: ParseOpts) -> NifResult<Term<'a>> {
+    let (html, encoding) = decode_input(binary.as_slice(), &opts)?;
 
     // Average node size based on web archive is 600:
     // https://discuss.httparchive.org/t/are-there-any-stats-about-dom-sizes-in-modern-pages/1619
     // So using a bit more than that.
     let arena = typed_arena::Arena::with_capacity(800);
 
-    let first_node = arena_sink::html5ever_parse_slice_into_arena(utf8.as_bytes(), &arena);
-    arena_sink::nodes_to_flat_term(env, first_node, attributes_as_maps)
+    let parsed = arena_sink::html5ever_parse_slice_into_arena(html.as_bytes(), &arena, &opts)?;
+    let term = arena_sink::nodes_to_flat_term(env, parsed.document, parsed.quirks_mode, &opts)?;
+
+    Ok(flat_result(env, term, &parsed, encoding, &opts)?)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_xml<'a>(env: Env<'a>, binary: Binary, opts: ParseOpts) -> NifResult<Term<'a>> {
+    let (xml, encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // See the comment in `parse` about the capacity.
+    let arena = typed_arena::Arena::with_capacity(800);
+
+    let parsed = arena_sink::xml5ever_parse_slice_into_arena(xml.as_bytes(), &arena, &opts)?;
+    let term = arena_sink::nodes_to_term(env, parsed.document, &opts);
+
+    Ok(nested_result(env, term, &parsed, encoding, &opts)?)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn flat_parse_xml<'a>(env: Env<'a>, binary: Binary, opts: ParseOpts) -> NifResult<Term<'a>> {
+    let (xml, encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // See the comment in `parse` about the capacity.
+    let arena = typed_arena::Arena::with_capacity(800);
+
+    let parsed = arena_sink::xml5ever_parse_slice_into_arena(xml.as_bytes(), &arena, &opts)?;
+    let term = arena_sink::nodes_to_flat_term(env, parsed.document, parsed.quirks_mode, &opts)?;
+
+    Ok(flat_result(env, term, &parsed, encoding, &opts)?)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_fragment<'a>(
+    env: Env<'a>,
+    binary: Binary,
+    context: &str,
+    opts: ParseOpts,
+) -> NifResult<Term<'a>> {
+    let (html, encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // Fragments are usually small, so we don't need a big arena upfront.
+    let arena = typed_arena::Arena::new();
+
+    let parsed = arena_sink::html5ever_parse_fragment_slice_into_arena(
+        html.as_bytes(),
+        context,
+        &arena,
+        &opts,
+    )?;
+    let term = arena_sink::nodes_to_term(env, parsed.document, &opts);
+
+    Ok(nested_result(env, term, &parsed, encoding, &opts)?)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn flat_parse_fragment<'a>(
+    env: Env<'a>,
+    binary: Binary,
+    context: &str,
+    opts: ParseOpts,
+) -> NifResult<Term<'a>> {
+    let (html, encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // Fragments are usually small, so we don't need a big arena upfront.
+    let arena = typed_arena::Arena::new();
+
+    let parsed = arena_sink::html5ever_parse_fragment_slice_into_arena(
+        html.as_bytes(),
+        context,
+        &arena,
+        &opts,
+    )?;
+    let term = arena_sink::nodes_to_flat_term(env, parsed.document, parsed.quirks_mode, &opts)?;
+
+    Ok(flat_result(env, term, &parsed, encoding, &opts)?)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn serialize<'a>(env: Env<'a>, tree: Term<'a>) -> NifResult<Term<'a>> {
+    let arena = typed_arena::Arena::new();
+    let document = serializer::nested_term_to_nodes(tree, &arena)?;
+    let html = serializer::node_to_html(document);
+
+    Ok((atoms::ok(), html).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn flat_serialize<'a>(env: Env<'a>, nodes: Term<'a>, id: Term<'a>) -> NifResult<Term<'a>> {
+    let arena = typed_arena::Arena::new();
+    let node = serializer::flat_term_to_nodes(nodes, id, &arena)?;
+    let html = serializer::node_to_html(node);
+
+    Ok((atoms::ok(), html).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn select<'a>(
+    env: Env<'a>,
+    binary: Binary,
+    selector: &str,
+    opts: ParseOpts,
+) -> NifResult<Term<'a>> {
+    let selectors = SelectorList::parse(selector)?;
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // See the comment in `parse` about the capacity.
+    let arena = typed_arena::Arena::with_capacity(800);
+
+    let parsed = arena_sink::html5ever_parse_slice_into_arena(html.as_bytes(), &arena, &opts)?;
+    let terms: Vec<Term> = selector::select(parsed.document, &selectors, opts.template_contents)
+        .into_iter()
+        .map(|node| arena_sink::nodes_to_term(env, node, &opts))
+        .collect();
+
+    Ok((atoms::ok(), terms).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn xpath<'a>(
+    env: Env<'a>,
+    binary: Binary,
+    expression: &str,
+    opts: ParseOpts,
+) -> NifResult<Term<'a>> {
+    let xpath = XPath::parse(expression)?;
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // See the comment in `parse` about the capacity.
+    let arena = typed_arena::Arena::with_capacity(800);
+
+    let parsed = arena_sink::html5ever_parse_slice_into_arena(html.as_bytes(), &arena, &opts)?;
+    let value = xpath::evaluate(&xpath, parsed.document, opts.template_contents)?;
+
+    Ok((atoms::ok(), xpath::value_to_term(env, &value, &opts)).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn text_content<'a>(env: Env<'a>, binary: Binary, opts: ParseOpts) -> NifResult<Term<'a>> {
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // See the comment in `parse` about the capacity.
+    let arena = typed_arena::Arena::with_capacity(800);
+
+    let parsed = arena_sink::html5ever_parse_slice_into_arena(html.as_bytes(), &arena, &opts)?;
+
+    Ok((atoms::ok(), text::text_content(parsed.document)).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sanitize<'a>(
+    env: Env<'a>,
+    binary: Binary,
+    policy: SanitizePolicy,
+    opts: ParseOpts,
+) -> NifResult<Term<'a>> {
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // Fragments are usually small, so we don't need a big arena upfront.
+    let arena = typed_arena::Arena::new();
+
+    let parsed = arena_sink::html5ever_parse_fragment_slice_into_arena(
+        html.as_bytes(),
+        "body",
+        &arena,
+        &opts,
+    )?;
+    sanitizer::sanitize(parsed.document, &policy);
+
+    Ok((atoms::ok(), serializer::node_to_html(parsed.document)).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sanitize_to_tree<'a>(
+    env: Env<'a>,
+    binary: Binary,
+    policy: SanitizePolicy,
+    opts: ParseOpts,
+) -> NifResult<Term<'a>> {
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // Fragments are usually small, so we don't need a big arena upfront.
+    let arena = typed_arena::Arena::new();
+
+    let parsed = arena_sink::html5ever_parse_fragment_slice_into_arena(
+        html.as_bytes(),
+        "body",
+        &arena,
+        &opts,
+    )?;
+    sanitizer::sanitize(parsed.document, &policy);
+
+    Ok((
+        atoms::ok(),
+        arena_sink::nodes_to_term(env, parsed.document, &opts),
+    )
+        .encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn rewrite<'a>(
+    env: Env<'a>,
+    binary: Binary,
+    rules: Vec<(String, Vec<Action>)>,
+    opts: ParseOpts,
+) -> NifResult<Term<'a>> {
+    let rules = rules
+        .into_iter()
+        .map(|(selector, actions)| Rule::new(&selector, actions))
+        .collect::<Result<Vec<Rule>, _>>()?;
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    Ok((atoms::ok(), rewriter::rewrite(&html, &rules)).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_links<'a>(
+    env: Env<'a>,
+    binary: Binary,
+    base_url: &str,
+    opts: ParseOpts,
+) -> NifResult<Term<'a>> {
+    let base_url = url::Url::parse(base_url)
+        .map_err(|_| Html5everExError::InvalidUrl(base_url.to_string()))?;
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // See the comment in `parse` about the capacity.
+    let arena = typed_arena::Arena::with_capacity(800);
+
+    let parsed = arena_sink::html5ever_parse_slice_into_arena(html.as_bytes(), &arena, &opts)?;
+    let links: Vec<Term> = links::extract_links(parsed.document, &base_url, opts.template_contents)
+        .iter()
+        .map(|link| link_to_term(env, link))
+        .collect::<Result<_, _>>()?;
+
+    Ok((atoms::ok(), links).encode(env))
+}
+
+fn link_to_term<'a>(env: Env<'a>, link: &links::Link) -> Result<Term<'a>, Html5everExError> {
+    let (element, attribute): (&str, &str) = (&link.element, &link.attribute);
+    let pairs = [
+        (atoms::element().encode(env), element.encode(env)),
+        (atoms::attribute().encode(env), attribute.encode(env)),
+        (atoms::url().encode(env), link.url.as_str().encode(env)),
+    ];
+
+    Term::map_from_pairs(env, &pairs).map_err(|_| Html5everExError::MapEntry)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn metadata<'a>(env: Env<'a>, binary: Binary, opts: ParseOpts) -> NifResult<Term<'a>> {
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    // See the comment in `parse` about the capacity.
+    let arena = typed_arena::Arena::with_capacity(800);
+
+    let parsed = arena_sink::html5ever_parse_slice_into_arena(html.as_bytes(), &arena, &opts)?;
+
+    Ok((
+        atoms::ok(),
+        metadata::metadata(parsed.document).to_term(env),
+    )
+        .encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn tokenize<'a>(env: Env<'a>, binary: Binary, opts: ParseOpts) -> NifResult<Term<'a>> {
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+
+    Ok((atoms::ok(), token_sink::tokenize(env, &html, &opts)).encode(env))
+}
+
+#[rustler::nif]
+fn parser_new<'a>(env: Env<'a>, opts: ParseOpts) -> Term<'a> {
+    (atoms::ok(), ParserResource::new(opts)).encode(env)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parser_feed(resource: ResourceArc<ParserResource>, chunk: Binary) -> NifResult<Atom> {
+    let mut state = resource.lock();
+    let state = state.as_mut().ok_or(Html5everExError::ParserFinished)?;
+
+    state.feed(chunk.as_slice())?;
+
+    Ok(atoms::ok())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parser_finish<'a>(env: Env<'a>, resource: ResourceArc<ParserResource>) -> NifResult<Term<'a>> {
+    let state = resource
+        .lock()
+        .take()
+        .ok_or(Html5everExError::ParserFinished)?;
+
+    Ok(state.finish(env)?)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_to_resource<'a>(env: Env<'a>, binary: Binary, opts: ParseOpts) -> NifResult<Term<'a>> {
+    let (html, _encoding) = decode_input(binary.as_slice(), &opts)?;
+    let resource = DocumentResource::parse(html.as_bytes(), opts)?;
+
+    Ok((atoms::ok(), resource).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn resource_query<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<DocumentResource>,
+    name: &str,
+) -> NifResult<Term<'a>> {
+    let ids = resource.lock().elements_by_name(name);
+
+    Ok((atoms::ok(), ids).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn resource_select<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<DocumentResource>,
+    selector: &str,
+) -> NifResult<Term<'a>> {
+    let selectors = SelectorList::parse(selector)?;
+    let ids = resource.lock().select(&selectors);
+
+    Ok((atoms::ok(), ids).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn resource_xpath<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<DocumentResource>,
+    expression: &str,
+) -> NifResult<Term<'a>> {
+    let xpath = XPath::parse(expression)?;
+    let result = resource.lock().xpath(env, &xpath)?;
+
+    Ok((atoms::ok(), result).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn resource_text<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<DocumentResource>,
+    id: usize,
+) -> NifResult<Term<'a>> {
+    let document = resource.lock();
+    let node = document
+        .node(id)
+        .ok_or(Html5everExError::NodeNotFound(id))?;
+
+    Ok((atoms::ok(), document.text(node)).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn resource_text_content<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<DocumentResource>,
+    id: usize,
+) -> NifResult<Term<'a>> {
+    let document = resource.lock();
+    let node = document
+        .node(id)
+        .ok_or(Html5everExError::NodeNotFound(id))?;
+
+    Ok((atoms::ok(), text::text_content(node)).encode(env))
+}
+
+#[rustler::nif]
+fn resource_attributes<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<DocumentResource>,
+    id: usize,
+) -> NifResult<Term<'a>> {
+    let document = resource.lock();
+    let node = document
+        .node(id)
+        .ok_or(Html5everExError::NodeNotFound(id))?;
+
+    match &node.data {
+        NodeData::Element { attrs, .. } => {
+            let attrs = arena_sink::attributes_to_term(env, &attrs.borrow(), &document.opts);
+            Ok((atoms::ok(), attrs).encode(env))
+        }
+        _ => Err(Html5everExError::NotAnElement(id).into()),
+    }
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn resource_serialize<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<DocumentResource>,
+    id: usize,
+) -> NifResult<Term<'a>> {
+    let document = resource.lock();
+    let node = document
+        .node(id)
+        .ok_or(Html5everExError::NodeNotFound(id))?;
+
+    Ok((atoms::ok(), serializer::node_to_html(node)).encode(env))
+}
+
+/// Returns the document as a UTF-8 string. In case the detection of the
+/// encoding was requested, it is decoded from the detected encoding,
+/// which is returned as well. Invalid sequences are replaced by U+FFFD
+/// when decoding, and in the lossy mode.
+fn decode_input<'a>(
+    bytes: &'a [u8],
+    opts: &ParseOpts,
+) -> Result<(Cow<'a, str>, Option<&'static Encoding>), Html5everExError> {
+    if let Some(max) = opts.max_input_bytes.filter(|&max| bytes.len() > max) {
+        return Err(Html5everExError::LimitExceeded(Limit::MaxInputBytes, max));
+    }
+
+    if opts.detect_encoding || opts.transport_encoding.is_some() {
+        let (encoding, bom_length) = encoding::sniff(bytes, opts.transport_encoding.as_deref());
+        let (html, _had_errors) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+
+        Ok((html, Some(encoding)))
+    } else if opts.lossy_utf8 {
+        Ok((String::from_utf8_lossy(bytes), None))
+    } else {
+        Ok((Cow::Borrowed(std::str::from_utf8(bytes)?), None))
+    }
+}
+
+/// Returns `{:ok, tree}`, or `{:ok, tree, info}` in case any information
+/// about the parsing was requested. The info is a map.
+fn nested_result<'a>(
+    env: Env<'a>,
+    tree: Term<'a>,
+    parsed: &arena_sink::ParsedDocument,
+    encoding: Option<&'static Encoding>,
+    opts: &ParseOpts,
+) -> Result<Term<'a>, Html5everExError> {
+    let mut pairs = info_pairs(env, parsed, encoding, opts)?;
+
+    // The flat results always have the quirks mode in the document node.
+    if opts.quirks_mode {
+        pairs.push((
+            atoms::quirks_mode().encode(env),
+            QuirksModeWrapper(parsed.quirks_mode).encode(env),
+        ));
+    }
+
+    if pairs.is_empty() {
+        Ok((atoms::ok(), tree).encode(env))
+    } else {
+        let info = Term::map_from_pairs(env, &pairs).map_err(|_| Html5everExError::MapEntry)?;
+        Ok((atoms::ok(), tree, info).encode(env))
+    }
+}
+
+/// Returns `{:ok, map}`, with the requested information about the
+/// parsing put in the same map as the nodes.
+fn flat_result<'a>(
+    env: Env<'a>,
+    mut map: Term<'a>,
+    parsed: &arena_sink::ParsedDocument,
+    encoding: Option<&'static Encoding>,
+    opts: &ParseOpts,
+) -> Result<Term<'a>, Html5everExError> {
+    for (key, value) in info_pairs(env, parsed, encoding, opts)? {
+        map = map
+            .map_put(key, value)
+            .map_err(|_| Html5everExError::MapEntry)?;
+    }
+
+    Ok((atoms::ok(), map).encode(env))
+}
+
+fn info_pairs<'a>(
+    env: Env<'a>,
+    parsed: &arena_sink::ParsedDocument,
+    encoding: Option<&'static Encoding>,
+    opts: &ParseOpts,
+) -> Result<Vec<(Term<'a>, Term<'a>)>, Html5everExError> {
+    let mut pairs = Vec::new();
+
+    if opts.parse_errors {
+        pairs.push((
+            atoms::errors().encode(env),
+            arena_sink::parse_errors_to_term(env, &parsed.errors)?,
+        ));
+    }
+
+    if let Some(encoding) = encoding {
+        pairs.push((atoms::encoding().encode(env), encoding.name().encode(env)));
+    }
+
+    Ok(pairs)
 }
 
 rustler::init!("Elixir.Html5ever.Native");
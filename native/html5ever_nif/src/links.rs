@@ -0,0 +1,243 @@
+// Extraction of the URLs referenced by a document, like the links and the
+// resources a crawler follows.
+//
+// The URLs are resolved against the base URL of the document, which is the
+// `href` of the first `<base>` element resolved against the given URL, like
+// browsers do:
+// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#document-base-url
+
+use html5ever::{Attribute, LocalName, QualName, local_name, ns};
+use url::Url;
+
+use crate::arena_sink::{Node, NodeData};
+use crate::selector::descendant_elements;
+
+pub(crate) struct Link {
+    pub(crate) element: LocalName,
+    pub(crate) attribute: LocalName,
+    pub(crate) url: Url,
+}
+
+/// Returns the URLs of the document, in document order. The URLs that
+/// cannot be resolved are skipped.
+pub(crate) fn extract_links(document: &Node, base_url: &Url, template_contents: bool) -> Vec<Link> {
+    let elements = descendant_elements(document, template_contents);
+    let base_url = document_base_url(&elements, base_url);
+    let mut links = Vec::new();
+
+    for element in elements {
+        let NodeData::Element { name, attrs, .. } = &element.data else {
+            continue;
+        };
+        let attrs = attrs.borrow();
+
+        for attr in attrs.iter().filter(|attr| attr.name.ns == ns!()) {
+            for url in attribute_urls(name, &attrs, attr) {
+                if let Ok(url) = base_url.join(url) {
+                    links.push(Link {
+                        element: name.local.clone(),
+                        attribute: attr.name.local.clone(),
+                        url,
+                    });
+                }
+            }
+        }
+    }
+
+    links
+}
+
+fn document_base_url(elements: &[&Node], base_url: &Url) -> Url {
+    let href = elements.iter().find_map(|element| match &element.data {
+        NodeData::Element { name, attrs, .. }
+            if name.ns == ns!(html) && name.local == local_name!("base") =>
+        {
+            attribute_value(&attrs.borrow(), local_name!("href"))
+        }
+        _ => None,
+    });
+
+    href.and_then(|href| base_url.join(&href).ok())
+        .unwrap_or_else(|| base_url.clone())
+}
+
+/// Returns the URLs in the value of the attribute, which are not resolved.
+fn attribute_urls<'a>(
+    element: &QualName,
+    attrs: &[Attribute],
+    attr: &'a Attribute,
+) -> Vec<&'a str> {
+    let value: &str = &attr.value;
+
+    // The styles of all the elements can have URLs, like backgrounds.
+    if attr.name.local == local_name!("style") {
+        return css_urls(value);
+    }
+
+    if element.ns != ns!(html) {
+        return vec![];
+    }
+
+    match (element.local.clone(), attr.name.local.clone()) {
+        (local_name!("a") | local_name!("area") | local_name!("link"), local_name!("href"))
+        | (
+            local_name!("img")
+            | local_name!("script")
+            | local_name!("iframe")
+            | local_name!("frame")
+            | local_name!("embed")
+            | local_name!("source")
+            | local_name!("video")
+            | local_name!("audio")
+            | local_name!("track")
+            | local_name!("input"),
+            local_name!("src"),
+        )
+        | (local_name!("video"), local_name!("poster"))
+        | (local_name!("object"), local_name!("data"))
+        | (local_name!("form"), local_name!("action"))
+        | (local_name!("button") | local_name!("input"), local_name!("formaction")) => {
+            vec![value]
+        }
+        (local_name!("img") | local_name!("source"), local_name!("srcset")) => srcset_urls(value),
+        (local_name!("meta"), local_name!("content")) if is_refresh(attrs) => {
+            refresh_url(value).into_iter().collect()
+        }
+        _ => vec![],
+    }
+}
+
+fn is_refresh(attrs: &[Attribute]) -> bool {
+    attribute_value(attrs, local_name!("http-equiv"))
+        .is_some_and(|value| value.eq_ignore_ascii_case("refresh"))
+}
+
+/// Returns the URLs of the image candidates of a `srcset`, following
+/// the algorithm of the spec, but without validating the descriptors:
+/// https://html.spec.whatwg.org/multipage/images.html#parse-a-srcset-attribute
+fn srcset_urls(srcset: &str) -> Vec<&str> {
+    let mut urls = Vec::new();
+    let mut rest = srcset;
+
+    loop {
+        rest = rest.trim_start_matches(|c| is_space(c) || c == ',');
+
+        if rest.is_empty() {
+            return urls;
+        }
+
+        let (url, after) = rest.split_at(rest.find(is_space).unwrap_or(rest.len()));
+        let trimmed = url.trim_end_matches(',');
+        urls.push(trimmed);
+
+        // A comma at the end of the URL ends the candidate, which has no
+        // descriptors. Otherwise they go until the next comma that is not
+        // in parentheses.
+        rest = if trimmed.len() < url.len() {
+            after
+        } else {
+            skip_descriptors(after)
+        };
+    }
+}
+
+fn skip_descriptors(descriptors: &str) -> &str {
+    let mut in_parens = false;
+
+    for (i, c) in descriptors.char_indices() {
+        match c {
+            '(' => in_parens = true,
+            ')' => in_parens = false,
+            ',' if !in_parens => return &descriptors[i + 1..],
+            _ => {}
+        }
+    }
+
+    ""
+}
+
+/// Returns the URL of the `content` of a `<meta http-equiv="refresh">`,
+/// like `5; url=/next`, following the algorithm of the spec:
+/// https://html.spec.whatwg.org/multipage/semantics.html#shared-declarative-refresh-steps
+fn refresh_url(content: &str) -> Option<&str> {
+    let rest = content.trim_start_matches(is_space);
+    let time = rest.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    if time.len() == rest.len() {
+        return None;
+    }
+
+    let rest = time.trim_start_matches(is_space);
+    let rest = rest.strip_prefix([';', ',']).unwrap_or(rest);
+    let rest = rest.trim_start_matches(is_space);
+
+    // The `url=` is optional, so a value without it is the URL itself.
+    let rest = match rest.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url") => {
+            match rest[3..].trim_start_matches(is_space).strip_prefix('=') {
+                Some(value) => value.trim_start_matches(is_space),
+                None => rest,
+            }
+        }
+        _ => rest,
+    };
+
+    let url = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let url = &rest[1..];
+            url.split(quote).next().unwrap_or(url)
+        }
+        _ => rest,
+    };
+
+    (!url.is_empty()).then_some(url)
+}
+
+/// Returns the URLs of the `url()` functions of CSS declarations.
+/// Escapes are not supported, since they are rare in URLs.
+fn css_urls(css: &str) -> Vec<&str> {
+    let mut urls = Vec::new();
+    let mut rest = css;
+
+    while let Some(start) = find_ignore_case(rest, "url(") {
+        let value = rest[start + 4..].trim_start_matches(is_space);
+
+        let (url, end) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => match value[1..].find(quote) {
+                Some(end) => (&value[1..end + 1], end + 2),
+                None => (&value[1..], value.len()),
+            },
+            _ => {
+                let end = value.find(')').unwrap_or(value.len());
+                (value[..end].trim_end_matches(is_space), end)
+            }
+        };
+
+        if !url.is_empty() {
+            urls.push(url);
+        }
+
+        rest = &value[end..];
+    }
+
+    urls
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+fn attribute_value(attrs: &[Attribute], name: LocalName) -> Option<String> {
+    attrs
+        .iter()
+        .find(|attr| attr.name.ns == ns!() && attr.name.local == name)
+        .map(|attr| attr.value.to_string())
+}
+
+/// The ASCII whitespace of the spec, which does not include U+000B.
+fn is_space(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
@@ -0,0 +1,149 @@
+// Extraction of the metadata of a document, like the title and the meta tags
+// used for link previews. Only the elements with metadata are read, so the
+// document is never converted to Elixir terms.
+
+use encoding_rs::Encoding;
+use html5ever::{Attribute, LocalName, local_name, ns};
+use rustler::{Encoder, Env, Term};
+
+use crate::arena_sink::{Node, NodeData, get_children};
+use crate::common::atoms;
+use crate::encoding::extract_encoding_from_content;
+use crate::selector::descendant_elements;
+
+#[derive(Default)]
+pub(crate) struct Metadata {
+    title: Option<String>,
+    lang: Option<String>,
+    charset: Option<&'static Encoding>,
+    canonical: Option<String>,
+    /// The `content` of the meta tags by their `name` or `property`,
+    /// which are lowercase, in document order.
+    meta: Vec<(String, String)>,
+    json_ld: Vec<String>,
+}
+
+/// Returns the metadata of the document. When there is more than one title,
+/// charset or canonical link, the first one is used, like browsers do.
+pub(crate) fn metadata(document: &Node) -> Metadata {
+    let mut metadata = Metadata::default();
+
+    for element in descendant_elements(document, false) {
+        let NodeData::Element { name, attrs, .. } = &element.data else {
+            continue;
+        };
+
+        // Titles and scripts in SVG are not metadata of the document.
+        if name.ns != ns!(html) {
+            continue;
+        }
+
+        let attrs = attrs.borrow();
+
+        match name.local {
+            local_name!("html") => metadata.lang = attribute_value(&attrs, local_name!("lang")),
+            local_name!("title") if metadata.title.is_none() => {
+                metadata.title = Some(collapse_whitespace(&child_text(element)));
+            }
+            local_name!("meta") => metadata.add_meta(&attrs),
+            local_name!("link") if metadata.canonical.is_none() && is_canonical(&attrs) => {
+                metadata.canonical = attribute_value(&attrs, local_name!("href"));
+            }
+            local_name!("script") if is_json_ld(&attrs) => {
+                metadata.json_ld.push(child_text(element));
+            }
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+impl Metadata {
+    fn add_meta(&mut self, attrs: &[Attribute]) {
+        if self.charset.is_none() {
+            self.charset = meta_charset(attrs);
+        }
+
+        let Some(content) = attribute_value(attrs, local_name!("content")) else {
+            return;
+        };
+
+        // Open Graph uses `property`, while the others use `name`.
+        for key in [local_name!("name"), local_name!("property")] {
+            if let Some(key) = attribute_value(attrs, key) {
+                self.meta.push((key.to_ascii_lowercase(), content.clone()));
+            }
+        }
+    }
+
+    pub(crate) fn to_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let pairs = [
+            (atoms::title().encode(env), self.title.encode(env)),
+            (atoms::lang().encode(env), self.lang.encode(env)),
+            (
+                atoms::charset().encode(env),
+                self.charset.map(Encoding::name).encode(env),
+            ),
+            (atoms::canonical().encode(env), self.canonical.encode(env)),
+            (atoms::meta().encode(env), self.meta.encode(env)),
+            (atoms::json_ld().encode(env), self.json_ld.encode(env)),
+        ];
+
+        Term::map_from_pairs(env, &pairs).expect("the keys are unique")
+    }
+}
+
+/// The encoding declared by `<meta charset>`, or by the `content` of
+/// `<meta http-equiv="content-type">`, when it is a known label.
+fn meta_charset(attrs: &[Attribute]) -> Option<&'static Encoding> {
+    if let Some(charset) = attribute_value(attrs, local_name!("charset")) {
+        return Encoding::for_label(charset.as_bytes());
+    }
+
+    let http_equiv = attribute_value(attrs, local_name!("http-equiv"))?;
+
+    if !http_equiv.eq_ignore_ascii_case("content-type") {
+        return None;
+    }
+
+    extract_encoding_from_content(attribute_value(attrs, local_name!("content"))?.as_bytes())
+}
+
+/// The `rel` attribute is a set of keywords separated by spaces.
+fn is_canonical(attrs: &[Attribute]) -> bool {
+    attribute_value(attrs, local_name!("rel")).is_some_and(|rel| {
+        rel.split_ascii_whitespace()
+            .any(|keyword| keyword.eq_ignore_ascii_case("canonical"))
+    })
+}
+
+fn is_json_ld(attrs: &[Attribute]) -> bool {
+    attribute_value(attrs, local_name!("type")).is_some_and(|kind| {
+        kind.trim_ascii()
+            .eq_ignore_ascii_case("application/ld+json")
+    })
+}
+
+/// The text of the children of the element, without the text of the other
+/// descendants, like the "child text content" of the spec.
+fn child_text(element: &Node) -> String {
+    get_children(element)
+        .iter()
+        .filter_map(|child| match &child.data {
+            NodeData::Text { contents } => Some(contents.borrow().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn attribute_value(attrs: &[Attribute], name: LocalName) -> Option<String> {
+    attrs
+        .iter()
+        .find(|attr| attr.name.ns == ns!() && attr.name.local == name)
+        .map(|attr| attr.value.to_string())
+}
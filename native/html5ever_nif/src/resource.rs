@@ -0,0 +1,185 @@
+// Parsed documents kept alive in the BEAM as resources.
+//
+// The nodes of a document reference each other and live in an arena, so
+// the resource owns the arena and erases the lifetime of the references.
+// This is sound as long as the references never outlive the resource,
+// which is guaranteed by only lending them while the resource is locked.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use rustler::{Env, Resource, ResourceArc, Term};
+
+use crate::arena_sink::{self, Node, NodeData, Ref, get_children};
+use crate::selector::{self, SelectorList};
+use crate::xpath::{self, XPath};
+use crate::{Html5everExError, ParseOpts};
+
+pub(crate) struct DocumentResource {
+    document: Mutex<Document>,
+}
+
+#[rustler::resource_impl]
+impl Resource for DocumentResource {}
+
+pub(crate) struct Document {
+    // The references must be dropped before the arena.
+    nodes: HashMap<usize, Ref<'static>>,
+    root: Ref<'static>,
+    pub(crate) opts: ParseOpts,
+    _arena: Box<typed_arena::Arena<Node<'static>>>,
+}
+
+// SAFETY: the nodes are not thread safe, because of the cells and tendrils,
+// but all of them are owned by the document, which is only accessed by one
+// thread at a time through the mutex.
+unsafe impl Send for Document {}
+
+impl DocumentResource {
+    pub(crate) fn parse(
+        html: &[u8],
+        opts: ParseOpts,
+    ) -> Result<ResourceArc<DocumentResource>, Html5everExError> {
+        // Average node size based on web archive is 600:
+        // https://discuss.httparchive.org/t/are-there-any-stats-about-dom-sizes-in-modern-pages/1619
+        // So using a bit more than that.
+        let arena = Box::new(typed_arena::Arena::with_capacity(800));
+
+        // SAFETY: the arena is in the heap, so moving the box does not move
+        // the nodes, and the arena is dropped only with the document.
+        let arena_ref: &'static typed_arena::Arena<Node<'static>> =
+            unsafe { &*(&*arena as *const typed_arena::Arena<Node<'static>>) };
+
+        let parsed = arena_sink::html5ever_parse_slice_into_arena(html, arena_ref, &opts)?;
+
+        Ok(ResourceArc::new(DocumentResource {
+            document: Mutex::new(Document {
+                nodes: index_nodes(parsed.document),
+                root: parsed.document,
+                opts,
+                _arena: arena,
+            }),
+        }))
+    }
+
+    /// Locks the document. A panic while it was locked does not change the
+    /// nodes, so the lock is recovered when poisoned.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Document> {
+        self.document.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Document {
+    pub(crate) fn node(&self, id: usize) -> Option<&Node<'static>> {
+        self.nodes.get(&id).copied()
+    }
+
+    /// Returns the IDs of the elements with the given name, in the order
+    /// they appear in the document.
+    pub(crate) fn elements_by_name(&self, local: &str) -> Vec<usize> {
+        let mut ids = Vec::new();
+        let mut nodes = vec![self.root];
+
+        while let Some(node) = nodes.pop() {
+            if let NodeData::Element {
+                name,
+                template_contents,
+                ..
+            } = &node.data
+            {
+                if &*name.local == local {
+                    ids.push(node.id);
+                }
+
+                // The contents come after the children, which templates usually don't have.
+                if let Some(contents) = template_contents.filter(|_| self.opts.template_contents) {
+                    nodes.extend(get_children(contents).into_iter().rev());
+                }
+            }
+
+            nodes.extend(get_children(node).into_iter().rev());
+        }
+
+        ids
+    }
+
+    /// Returns the IDs of the elements matched by the selectors,
+    /// in the order they appear in the document.
+    pub(crate) fn select(&self, selectors: &SelectorList) -> Vec<usize> {
+        selector::select(self.root, selectors, self.opts.template_contents)
+            .into_iter()
+            .map(|node| node.id)
+            .collect()
+    }
+
+    /// Evaluates the expression with the document as the context node.
+    /// The nodes in the result are encoded with their IDs, which is done
+    /// here because the nodes cannot leave the document.
+    pub(crate) fn xpath<'a>(
+        &self,
+        env: Env<'a>,
+        xpath: &XPath,
+    ) -> Result<Term<'a>, Html5everExError> {
+        let value = xpath::evaluate(xpath, self.root, self.opts.template_contents)?;
+
+        Ok(xpath::value_to_ids_term(env, &value, &self.opts))
+    }
+
+    /// Returns the text of the node, like the `textContent` property of the DOM.
+    /// For documents and elements, this is the text of all their descendants.
+    pub(crate) fn text(&self, node: &Node) -> String {
+        if let NodeData::Comment { contents } | NodeData::ProcessingInstruction { contents, .. } =
+            &node.data
+        {
+            return contents.to_string();
+        }
+
+        let mut text = String::new();
+        let mut nodes = vec![node];
+
+        while let Some(node) = nodes.pop() {
+            match &node.data {
+                NodeData::Text { contents } => text.push_str(&contents.borrow()),
+                NodeData::Element {
+                    template_contents, ..
+                } => {
+                    if let Some(contents) =
+                        template_contents.filter(|_| self.opts.template_contents)
+                    {
+                        nodes.extend(get_children(contents).into_iter().rev());
+                    }
+
+                    nodes.extend(get_children(node).into_iter().rev());
+                }
+                NodeData::Document | NodeData::DocumentFragment => {
+                    nodes.extend(get_children(node).into_iter().rev())
+                }
+                _ => {}
+            }
+        }
+
+        text
+    }
+}
+
+/// Indexes the nodes in the tree by their IDs, including the contents of templates.
+fn index_nodes(root: Ref<'static>) -> HashMap<usize, Ref<'static>> {
+    let mut index = HashMap::new();
+    let mut nodes = vec![root];
+
+    while let Some(node) = nodes.pop() {
+        index.insert(node.id, node);
+
+        if let NodeData::Element {
+            template_contents: Some(contents),
+            ..
+        } = &node.data
+        {
+            nodes.push(contents);
+        }
+
+        nodes.extend(get_children(node));
+    }
+
+    index
+}
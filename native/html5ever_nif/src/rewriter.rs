@@ -0,0 +1,571 @@
+// Streaming rewriting of documents with rules made of CSS selectors and
+// actions, in the spirit of lol-html: https://github.com/cloudflare/lol-html
+//
+// The document is tokenized in a single pass, without building a tree, and
+// the source is copied to the output as it is, except for the elements that
+// the rules match. The tokenizer does not report where the tokens are in the
+// source, so their positions are taken from the input that is left in the
+// queue when they are emitted.
+//
+// The elements are matched against a stack of the open elements, which are
+// kept in an arena along with their previous siblings. The selectors that
+// depend on what comes after an element, like `:has()`, are not supported.
+// Without a tree builder, the end tags that are implied, like the one of a
+// `<p>` before a `<div>`, are only handled for the most common elements.
+
+use std::cell::{Cell, RefCell};
+
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::{
+    BufferQueue, Tag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer,
+};
+use html5ever::{Attribute, LocalName, QualName, local_name, ns};
+use rustler::NifTaggedEnum;
+
+use crate::Html5everExError;
+use crate::arena_sink::{Arena, Node, NodeData, Ref};
+use crate::selector::SelectorList;
+use crate::token_sink::ForeignContent;
+
+/// An action applied to the elements matched by a rule. The HTML of the
+/// actions is written as it is, without being parsed.
+#[derive(NifTaggedEnum)]
+pub(crate) enum Action {
+    SetAttribute(String, String),
+    RemoveAttribute(String),
+    SetInnerHtml(String),
+    SetInnerText(String),
+    Prepend(String),
+    Append(String),
+    Remove,
+}
+
+pub(crate) struct Rule {
+    selectors: SelectorList,
+    actions: Vec<Action>,
+}
+
+impl Rule {
+    pub(crate) fn new(selector: &str, actions: Vec<Action>) -> Result<Self, Html5everExError> {
+        let selectors = SelectorList::parse(selector)?;
+
+        if selectors.needs_lookahead() {
+            return Err(Html5everExError::InvalidSelector(format!(
+                "\"{selector}\" depends on the elements after the matched ones, \
+                 which is not supported when rewriting"
+            )));
+        }
+
+        Ok(Rule { selectors, actions })
+    }
+}
+
+/// Returns the HTML with the actions of the rules applied to the elements
+/// they match, in the order of the rules.
+pub(crate) fn rewrite(html: &str, rules: &[Rule]) -> String {
+    let arena = typed_arena::Arena::new();
+    let input = BufferQueue::default();
+    input.push_back(StrTendril::from_slice(html));
+
+    let tokenizer = Tokenizer::new(
+        RewriteSink::new(html, &input, rules, &arena),
+        Default::default(),
+    );
+
+    // The sink never blocks the tokenizer, so all the input is consumed.
+    let _ = tokenizer.feed(&input);
+    tokenizer.end();
+
+    tokenizer.sink.output.into_inner()
+}
+
+struct RewriteSink<'a, 'arena> {
+    source: &'a str,
+    /// The input of the tokenizer, with the part of the source that is left.
+    input: &'a BufferQueue,
+    rules: &'a [Rule],
+    arena: Arena<'arena>,
+    /// The parent of the elements that are not in other elements.
+    document: Ref<'arena>,
+    output: RefCell<String>,
+    /// The position in the source up to which it was written or skipped.
+    position: Cell<usize>,
+    /// The position of the input after the last token, ignoring the
+    /// parse errors, which are emitted in the middle of tokens.
+    token_end: Cell<usize>,
+    open_elements: RefCell<Vec<OpenElement<'arena>>>,
+    /// The number of open elements whose contents are not written, because
+    /// they were removed or replaced.
+    skipping: Cell<usize>,
+    foreign_content: ForeignContent,
+}
+
+struct OpenElement<'arena> {
+    node: Ref<'arena>,
+    name: LocalName,
+    /// The HTML written before the end tag.
+    append: String,
+    skips_contents: bool,
+    removed: bool,
+}
+
+/// The changes to a start tag and its contents, from the actions
+/// of the rules that match it.
+#[derive(Default)]
+struct Edit {
+    /// The attributes of the tag, when they are changed.
+    attrs: Option<Vec<Attribute>>,
+    /// The HTML that replaces the contents.
+    contents: Option<String>,
+    prepend: String,
+    append: String,
+    removed: bool,
+}
+
+impl<'a, 'arena> RewriteSink<'a, 'arena> {
+    fn new(
+        source: &'a str,
+        input: &'a BufferQueue,
+        rules: &'a [Rule],
+        arena: Arena<'arena>,
+    ) -> Self {
+        RewriteSink {
+            source,
+            input,
+            rules,
+            arena,
+            document: arena.alloc(Node::new(NodeData::Document, 0, 0)),
+            output: RefCell::new(String::with_capacity(source.len())),
+            position: Cell::new(0),
+            // The byte order mark is skipped without a token.
+            token_end: Cell::new(if source.starts_with('\u{feff}') { 3 } else { 0 }),
+            open_elements: RefCell::new(Vec::new()),
+            skipping: Cell::new(0),
+            foreign_content: ForeignContent::default(),
+        }
+    }
+
+    /// The position of the input that the tokenizer already consumed.
+    fn consumed(&self) -> usize {
+        let mut buffers = Vec::new();
+        while let Some(buffer) = self.input.pop_front() {
+            buffers.push(buffer);
+        }
+
+        let remaining: usize = buffers.iter().map(|buffer| buffer.len()).sum();
+        for buffer in buffers {
+            self.input.push_back(buffer);
+        }
+
+        self.source.len() - remaining
+    }
+
+    /// The position of the `<` that starts the token after the last one.
+    fn token_start(&self) -> usize {
+        let bytes = self.source.as_bytes();
+        let mut start = self.token_end.get();
+
+        // The line feed after a carriage return is skipped by the tokenizer.
+        if start > 0 && bytes[start - 1] == b'\r' && bytes.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+
+        // The characters before a tag can be emitted after its `<` was
+        // consumed, when the tokenizer finds out they are not a tag.
+        if bytes.get(start) != Some(&b'<') {
+            start -= 1;
+        }
+
+        // End tags without a name are dropped without a token.
+        while bytes[start..].starts_with(b"</>") {
+            start += 3;
+        }
+
+        debug_assert_eq!(bytes[start], b'<');
+        start
+    }
+
+    /// Writes the source that was not written yet, up to the position,
+    /// unless the contents of an element are skipped.
+    fn write_source(&self, end: usize) {
+        if self.skipping.get() == 0 {
+            let start = self.position.get();
+            self.output.borrow_mut().push_str(&self.source[start..end]);
+        }
+
+        self.position.set(end);
+    }
+
+    fn start_tag(&self, tag: &Tag, source: &str) {
+        let foreign = self.foreign_content.is_active()
+            || matches!(tag.name, local_name!("svg") | local_name!("math"));
+
+        if !foreign {
+            while self
+                .current_name()
+                .is_some_and(|current| closes_implicitly(&current, &tag.name))
+            {
+                self.close_element(None);
+            }
+        }
+
+        let parent = self.current_node().unwrap_or(self.document);
+        let node = self.arena.alloc(Node::new(
+            NodeData::Element {
+                // The names are lowercase, so they are matched like
+                // names of HTML elements in all the namespaces.
+                name: QualName::new(None, ns!(html), tag.name.clone()),
+                attrs: RefCell::new(tag.attrs.clone()),
+                template_contents: None,
+                mathml_annotation_xml_integration_point: false,
+            },
+            0,
+            0,
+        ));
+        parent.append(node);
+
+        // The elements in contents that are not written are not matched.
+        let mut edit = Edit::default();
+        if self.skipping.get() == 0 {
+            for rule in self
+                .rules
+                .iter()
+                .filter(|rule| rule.selectors.matches(node))
+            {
+                for action in &rule.actions {
+                    edit.apply(action, tag);
+                }
+            }
+        }
+
+        let has_contents = if foreign {
+            !tag.self_closing
+        } else {
+            !is_void(&tag.name)
+        };
+
+        if self.skipping.get() == 0 && !edit.removed {
+            let mut output = self.output.borrow_mut();
+
+            match &edit.attrs {
+                Some(attrs) => write_start_tag(&mut output, tag, attrs),
+                None => output.push_str(source),
+            }
+
+            if has_contents {
+                output.push_str(edit.contents.as_deref().unwrap_or(&edit.prepend));
+            }
+        }
+
+        if has_contents {
+            let skips_contents = edit.removed || edit.contents.is_some();
+
+            if skips_contents {
+                self.skipping.set(self.skipping.get() + 1);
+            }
+
+            self.open_elements.borrow_mut().push(OpenElement {
+                node,
+                name: tag.name.clone(),
+                append: edit.append,
+                skips_contents,
+                removed: edit.removed,
+            });
+        }
+    }
+
+    fn end_tag(&self, tag: &Tag, source: &str) {
+        let index = self
+            .open_elements
+            .borrow()
+            .iter()
+            .rposition(|element| element.name == tag.name);
+
+        let Some(index) = index else {
+            // End tags without a start tag are written as they are.
+            if self.skipping.get() == 0 {
+                self.output.borrow_mut().push_str(source);
+            }
+            return;
+        };
+
+        // The elements opened after it are closed without an end tag.
+        while self.open_elements.borrow().len() > index + 1 {
+            self.close_element(None);
+        }
+
+        self.close_element(Some(source));
+    }
+
+    /// Closes the current element, writing the end tag from the source,
+    /// which is `None` when the element is closed implicitly.
+    fn close_element(&self, end_tag: Option<&str>) {
+        let element = self
+            .open_elements
+            .borrow_mut()
+            .pop()
+            .expect("there is an open element");
+
+        if element.skips_contents {
+            self.skipping.set(self.skipping.get() - 1);
+        }
+
+        if self.skipping.get() > 0 || element.removed {
+            return;
+        }
+
+        let mut output = self.output.borrow_mut();
+        output.push_str(&element.append);
+        output.push_str(end_tag.unwrap_or_default());
+    }
+
+    fn current_node(&self) -> Option<Ref<'arena>> {
+        self.open_elements
+            .borrow()
+            .last()
+            .map(|element| element.node)
+    }
+
+    fn current_name(&self) -> Option<LocalName> {
+        self.open_elements
+            .borrow()
+            .last()
+            .map(|element| element.name.clone())
+    }
+}
+
+impl TokenSink for RewriteSink<'_, '_> {
+    type Handle = ();
+
+    fn process_token(&self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        let end = self.consumed();
+
+        match token {
+            // The characters are written from the source, with the next token.
+            Token::CharacterTokens(_) | Token::NullCharacterToken => self.token_end.set(end),
+            Token::ParseError(_) => {}
+            Token::EOFToken => {
+                self.write_source(self.source.len());
+
+                while !self.open_elements.borrow().is_empty() {
+                    self.close_element(None);
+                }
+            }
+            token => {
+                let start = self.token_start();
+                self.write_source(start);
+
+                let source = &self.source[start..end];
+                let mut result = TokenSinkResult::Continue;
+
+                match token {
+                    Token::TagToken(tag) if tag.kind == TagKind::StartTag => {
+                        self.start_tag(&tag, source);
+                        result = self.foreign_content.process_tag(&tag);
+                    }
+                    Token::TagToken(tag) => {
+                        self.end_tag(&tag, source);
+                        result = self.foreign_content.process_tag(&tag);
+                    }
+                    // Comments and doctypes are written as they are.
+                    _ => self.write_source(end),
+                }
+
+                self.position.set(end);
+                self.token_end.set(end);
+                return result;
+            }
+        }
+
+        TokenSinkResult::Continue
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.foreign_content.is_active()
+    }
+}
+
+impl Edit {
+    /// Applies the action after the ones before it, so the contents set by
+    /// an action replace the ones prepended or appended before.
+    fn apply(&mut self, action: &Action, tag: &Tag) {
+        match action {
+            Action::SetAttribute(name, value) => {
+                let name = LocalName::from(name.to_ascii_lowercase());
+                let value = StrTendril::from_slice(value);
+                let attrs = self.attrs.get_or_insert_with(|| tag.attrs.clone());
+
+                match attrs.iter_mut().find(|attr| attr.name.local == name) {
+                    Some(attr) => attr.value = value,
+                    None => attrs.push(Attribute {
+                        name: QualName::new(None, ns!(), name),
+                        value,
+                    }),
+                }
+            }
+            Action::RemoveAttribute(name) => {
+                let name = LocalName::from(name.to_ascii_lowercase());
+                let attrs = self.attrs.get_or_insert_with(|| tag.attrs.clone());
+                attrs.retain(|attr| attr.name.local != name);
+            }
+            Action::SetInnerHtml(html) => self.set_contents(html.clone()),
+            Action::SetInnerText(text) if is_raw_text(&tag.name) => self.set_contents(text.clone()),
+            Action::SetInnerText(text) => self.set_contents(escape(text, false)),
+            Action::Prepend(html) => match &mut self.contents {
+                Some(contents) => contents.insert_str(0, html),
+                None => self.prepend.insert_str(0, html),
+            },
+            Action::Append(html) => match &mut self.contents {
+                Some(contents) => contents.push_str(html),
+                None => self.append.push_str(html),
+            },
+            Action::Remove => self.removed = true,
+        }
+    }
+
+    fn set_contents(&mut self, contents: String) {
+        self.contents = Some(contents);
+        self.prepend.clear();
+        self.append.clear();
+    }
+}
+
+fn write_start_tag(output: &mut String, tag: &Tag, attrs: &[Attribute]) {
+    output.push('<');
+    output.push_str(&tag.name);
+
+    for attr in attrs {
+        output.push(' ');
+        output.push_str(&attr.name.local);
+        output.push_str("=\"");
+        output.push_str(&escape(&attr.value, true));
+        output.push('"');
+    }
+
+    if tag.self_closing {
+        output.push_str(" /");
+    }
+
+    output.push('>');
+}
+
+/// Escapes text like the serialization algorithm of the spec.
+fn escape(text: &str, attribute_mode: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '\u{A0}' => escaped.push_str("&nbsp;"),
+            '"' if attribute_mode => escaped.push_str("&quot;"),
+            '<' if !attribute_mode => escaped.push_str("&lt;"),
+            '>' if !attribute_mode => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn is_void(name: &LocalName) -> bool {
+    matches!(
+        *name,
+        local_name!("area")
+            | local_name!("base")
+            | local_name!("basefont")
+            | local_name!("bgsound")
+            | local_name!("br")
+            | local_name!("col")
+            | local_name!("embed")
+            | local_name!("frame")
+            | local_name!("hr")
+            | local_name!("img")
+            | local_name!("input")
+            | local_name!("keygen")
+            | local_name!("link")
+            | local_name!("meta")
+            | local_name!("param")
+            | local_name!("source")
+            | local_name!("track")
+            | local_name!("wbr")
+    )
+}
+
+/// The elements whose text is not escaped when serialized.
+fn is_raw_text(name: &LocalName) -> bool {
+    matches!(
+        *name,
+        local_name!("style")
+            | local_name!("script")
+            | local_name!("xmp")
+            | local_name!("iframe")
+            | local_name!("noembed")
+            | local_name!("noframes")
+            | local_name!("plaintext")
+            | local_name!("noscript")
+    )
+}
+
+/// Checks if the start tag closes the current element, for the most common
+/// end tags that can be omitted:
+/// https://html.spec.whatwg.org/multipage/syntax.html#optional-tags
+fn closes_implicitly(current: &LocalName, tag: &LocalName) -> bool {
+    match *current {
+        local_name!("p") => matches!(
+            *tag,
+            local_name!("address")
+                | local_name!("article")
+                | local_name!("aside")
+                | local_name!("blockquote")
+                | local_name!("details")
+                | local_name!("div")
+                | local_name!("dl")
+                | local_name!("fieldset")
+                | local_name!("figcaption")
+                | local_name!("figure")
+                | local_name!("footer")
+                | local_name!("form")
+                | local_name!("h1")
+                | local_name!("h2")
+                | local_name!("h3")
+                | local_name!("h4")
+                | local_name!("h5")
+                | local_name!("h6")
+                | local_name!("header")
+                | local_name!("hgroup")
+                | local_name!("hr")
+                | local_name!("main")
+                | local_name!("menu")
+                | local_name!("nav")
+                | local_name!("ol")
+                | local_name!("p")
+                | local_name!("pre")
+                | local_name!("section")
+                | local_name!("table")
+                | local_name!("ul")
+        ),
+        local_name!("li") => *tag == local_name!("li"),
+        local_name!("dt") | local_name!("dd") => {
+            matches!(*tag, local_name!("dt") | local_name!("dd"))
+        }
+        local_name!("option") => matches!(*tag, local_name!("option") | local_name!("optgroup")),
+        local_name!("optgroup") => *tag == local_name!("optgroup"),
+        local_name!("td") | local_name!("th") => {
+            matches!(
+                *tag,
+                local_name!("td") | local_name!("th") | local_name!("tr")
+            )
+        }
+        local_name!("tr") => matches!(
+            *tag,
+            local_name!("tr") | local_name!("tbody") | local_name!("thead") | local_name!("tfoot")
+        ),
+        local_name!("thead") | local_name!("tbody") | local_name!("tfoot") => {
+            matches!(
+                *tag,
+                local_name!("tbody") | local_name!("thead") | local_name!("tfoot")
+            )
+        }
+        _ => false,
+    }
+}
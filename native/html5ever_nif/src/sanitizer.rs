@@ -0,0 +1,162 @@
+// Sanitization of parsed HTML with an allowlist policy.
+//
+// The nodes are sanitized in the arena, right after parsing, so the result
+// can be serialized or encoded like any other parsed fragment. Since the
+// parser already resolved the markup, like entities and misnested tags,
+// the policy is applied to the same tree a browser would build.
+
+use std::collections::HashMap;
+
+use html5ever::Attribute;
+use rustler::NifMap;
+
+use crate::arena_sink::{Node, NodeData, get_children};
+
+/// The policy used to sanitize the nodes.
+/// All the keys are filled with defaults in the Elixir side.
+#[derive(NifMap)]
+pub(crate) struct SanitizePolicy {
+    /// The names of the elements that are kept.
+    tags: Vec<String>,
+    /// The names of the attributes that are kept, by element name.
+    /// The attributes under `"*"` are kept for all the elements.
+    attributes: HashMap<String, Vec<String>>,
+    /// The schemes allowed in URLs. Relative URLs are always allowed.
+    url_schemes: Vec<String>,
+    comments: bool,
+    /// The names of the elements that are removed with their contents.
+    /// Other elements that are not allowed are replaced by their contents.
+    drop_content: Vec<String>,
+}
+
+/// Sanitizes the descendants of the node, which is usually the
+/// document of a parsed fragment. Doctypes and processing
+/// instructions are always removed.
+pub(crate) fn sanitize(root: &Node, policy: &SanitizePolicy) {
+    let mut nodes = get_children(root);
+
+    while let Some(node) = nodes.pop() {
+        match &node.data {
+            NodeData::Element {
+                name,
+                attrs,
+                template_contents,
+                ..
+            } => {
+                let local = &*name.local;
+
+                if contains(&policy.drop_content, local) {
+                    node.detach();
+                    continue;
+                }
+
+                let mut children = get_children(node);
+                if let Some(contents) = template_contents {
+                    children.extend(get_children(contents));
+                }
+
+                if !contains(&policy.tags, local) {
+                    // The element is replaced by its children, and by the
+                    // contents of templates, which are sanitized afterwards.
+                    for child in &children {
+                        node.insert_before(child);
+                    }
+                    node.detach();
+                } else {
+                    attrs
+                        .borrow_mut()
+                        .retain(|attr| policy.allows_attribute(local, attr));
+                }
+
+                nodes.extend(children);
+            }
+            NodeData::Comment { .. } if policy.comments => {}
+            NodeData::Doctype { .. }
+            | NodeData::Comment { .. }
+            | NodeData::ProcessingInstruction { .. } => node.detach(),
+            NodeData::Text { .. } => {}
+            NodeData::Document | NodeData::DocumentFragment => {
+                nodes.extend(get_children(node));
+            }
+        }
+    }
+}
+
+impl SanitizePolicy {
+    fn allows_attribute(&self, element: &str, attr: &Attribute) -> bool {
+        let name = &*attr.name.local;
+        let allowed = [element, "*"].iter().any(|key| {
+            self.attributes
+                .get(*key)
+                .is_some_and(|names| contains(names, name))
+        });
+
+        if !allowed {
+            return false;
+        }
+
+        if name == "srcset" {
+            // The URL is the first part of each image candidate.
+            return attr.value.split(',').all(|candidate| {
+                let url = candidate.split_ascii_whitespace().next().unwrap_or("");
+                self.allows_url(url)
+            });
+        }
+
+        !is_url_attribute(name) || self.allows_url(&attr.value)
+    }
+
+    fn allows_url(&self, url: &str) -> bool {
+        match url_scheme(url) {
+            Some(scheme) => self
+                .url_schemes
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(&scheme)),
+            None => true,
+        }
+    }
+}
+
+fn contains(names: &[String], name: &str) -> bool {
+    names.iter().any(|allowed| allowed == name)
+}
+
+/// Attributes whose values are URLs, in HTML, SVG and MathML.
+fn is_url_attribute(name: &str) -> bool {
+    matches!(
+        name,
+        "action"
+            | "background"
+            | "cite"
+            | "codebase"
+            | "data"
+            | "formaction"
+            | "href"
+            | "icon"
+            | "longdesc"
+            | "manifest"
+            | "ping"
+            | "poster"
+            | "src"
+    )
+}
+
+/// Returns the scheme of the URL, or `None` when it is relative.
+///
+/// Like the URL parser of browsers, leading and trailing spaces and
+/// control characters are ignored, along with tabs and newlines.
+fn url_scheme(url: &str) -> Option<String> {
+    let url: String = url
+        .trim_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+
+    let (scheme, _rest) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+
+    valid.then(|| scheme.to_string())
+}
@@ -0,0 +1,842 @@
+// CSS selectors, matched against the nodes in the arena.
+//
+// This implements a subset of the Selectors Level 4 spec:
+// https://www.w3.org/TR/selectors-4/
+//
+// It supports type, universal, class, ID and attribute selectors, all the
+// combinators, and the pseudo-classes that don't depend on the state of a
+// browser, like `:nth-child()`, `:not()` and `:has()`. Namespace prefixes
+// and pseudo-elements are not supported.
+
+use std::borrow::Cow;
+use std::ptr;
+
+use html5ever::{Attribute, ns};
+
+use crate::Html5everExError;
+use crate::arena_sink::{Node, NodeData, Ref, get_children};
+
+/// The maximum nesting of the pseudo-classes that take selectors, like
+/// `:not()`, which are parsed and matched recursively.
+const MAX_NESTING: usize = 32;
+
+/// A list of selectors separated by commas, which matches the
+/// elements matched by any of them.
+pub(crate) struct SelectorList(Vec<ComplexSelector>);
+
+/// Compound selectors separated by combinators, like `ul > li.item`.
+struct ComplexSelector {
+    /// The combinator before the first compound selector, which
+    /// only relative selectors have, like the ones in `:has(> img)`.
+    leading: Option<Combinator>,
+    compounds: Vec<Compound>,
+    /// The combinators between the compound selectors.
+    combinators: Vec<Combinator>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+/// A sequence of simple selectors that are not separated by a combinator,
+/// like `a.external[href]`.
+#[derive(Default)]
+struct Compound {
+    /// The name of the type selector. It is `None` for the universal selector.
+    name: Option<String>,
+    filters: Vec<Filter>,
+}
+
+enum Filter {
+    Id(String),
+    Class(String),
+    Attribute {
+        name: String,
+        matcher: Option<(AttributeOperator, String)>,
+        case_insensitive: bool,
+    },
+    /// The `:nth-child()` family of pseudo-classes, matching the elements
+    /// at the positions `a * n + b`, counting from 1.
+    Nth {
+        a: i64,
+        b: i64,
+        from_end: bool,
+        of_type: bool,
+    },
+    Root,
+    Empty,
+    Not(SelectorList),
+    Is(SelectorList),
+    Has(SelectorList),
+}
+
+enum AttributeOperator {
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+/// Returns the elements matched by the selectors among the descendants
+/// of the node, in the order they appear in the document.
+pub(crate) fn select<'arena>(
+    node: &Node<'arena>,
+    selectors: &SelectorList,
+    template_contents: bool,
+) -> Vec<Ref<'arena>> {
+    descendant_elements(node, template_contents)
+        .into_iter()
+        .filter(|element| selectors.matches(element))
+        .collect()
+}
+
+impl SelectorList {
+    pub(crate) fn parse(selector: &str) -> Result<Self, Html5everExError> {
+        let mut parser = Parser {
+            chars: selector.chars().collect(),
+            position: 0,
+            nesting: 0,
+        };
+
+        let selectors = parser.parse_list(false)?;
+
+        match parser.peek() {
+            Some(c) => Err(parser.error(format!("unexpected \"{c}\""))),
+            None => Ok(selectors),
+        }
+    }
+
+    pub(crate) fn matches(&self, element: &Node) -> bool {
+        self.0
+            .iter()
+            .any(|selector| selector.matches(element, None))
+    }
+
+    /// Checks if the selectors depend on the elements after the matched
+    /// ones, like `:has()` and `:last-child`, which is the case when they
+    /// are checked against their descendants or next siblings.
+    pub(crate) fn needs_lookahead(&self) -> bool {
+        self.0.iter().any(|selector| {
+            selector.compounds.iter().any(|compound| {
+                compound.filters.iter().any(|filter| match filter {
+                    Filter::Nth { from_end, .. } => *from_end,
+                    Filter::Empty | Filter::Has(_) => true,
+                    Filter::Not(selectors) | Filter::Is(selectors) => selectors.needs_lookahead(),
+                    _ => false,
+                })
+            })
+        })
+    }
+}
+
+impl ComplexSelector {
+    /// Checks if the element matches the selector. Relative selectors are
+    /// matched with an anchor element, which the leading combinator refers to.
+    fn matches<'arena>(&self, element: &Node<'arena>, anchor: Option<&Node<'arena>>) -> bool {
+        self.matches_from(element, self.compounds.len() - 1, anchor)
+    }
+
+    fn matches_from<'arena>(
+        &self,
+        element: &Node<'arena>,
+        index: usize,
+        anchor: Option<&Node<'arena>>,
+    ) -> bool {
+        if !self.compounds[index].matches(element) {
+            return false;
+        }
+
+        if index > 0 {
+            return any_related(element, self.combinators[index - 1], |related| {
+                self.matches_from(related, index - 1, anchor)
+            });
+        }
+
+        match (self.leading, anchor) {
+            (Some(combinator), Some(anchor)) => {
+                any_related(element, combinator, |related| ptr::eq(related, anchor))
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Checks if any of the elements that the combinator relates to the element
+/// satisfies the predicate. The related elements are the ones on the left
+/// side of the combinator, like the parent for the child combinator.
+fn any_related<'arena>(
+    element: &Node<'arena>,
+    combinator: Combinator,
+    mut predicate: impl FnMut(&Node<'arena>) -> bool,
+) -> bool {
+    let next = |node: &Node<'arena>| match combinator {
+        Combinator::Child | Combinator::Descendant => parent_element(node),
+        Combinator::NextSibling | Combinator::SubsequentSibling => previous_element_sibling(node),
+    };
+
+    if matches!(combinator, Combinator::Child | Combinator::NextSibling) {
+        return next(element).is_some_and(predicate);
+    }
+
+    let mut related = next(element);
+    while let Some(current) = related {
+        if predicate(current) {
+            return true;
+        }
+        related = next(current);
+    }
+
+    false
+}
+
+impl Compound {
+    fn matches(&self, element: &Node) -> bool {
+        let NodeData::Element { name, attrs, .. } = &element.data else {
+            return false;
+        };
+
+        // Names in HTML are case-insensitive, but not in SVG and MathML.
+        let is_html = name.ns == ns!(html);
+
+        if let Some(type_name) = &self.name {
+            let matches = if is_html {
+                (*name.local).eq_ignore_ascii_case(type_name)
+            } else {
+                &*name.local == type_name
+            };
+
+            if !matches {
+                return false;
+            }
+        }
+
+        let attrs = attrs.borrow();
+        self.filters
+            .iter()
+            .all(|filter| filter.matches(element, &attrs, is_html))
+    }
+}
+
+impl Filter {
+    fn matches(&self, element: &Node, attrs: &[Attribute], is_html: bool) -> bool {
+        match self {
+            Filter::Id(id) => attribute_value(attrs, "id", is_html) == Some(id.as_str()),
+            Filter::Class(class) => attribute_value(attrs, "class", is_html)
+                .is_some_and(|classes| classes.split_ascii_whitespace().any(|c| c == class)),
+            Filter::Attribute {
+                name,
+                matcher,
+                case_insensitive,
+            } => match (attribute_value(attrs, name, is_html), matcher) {
+                (Some(value), Some((operator, expected))) => {
+                    operator.matches(value, expected, *case_insensitive)
+                }
+                (value, None) => value.is_some(),
+                (None, Some(_)) => false,
+            },
+            Filter::Nth {
+                a,
+                b,
+                from_end,
+                of_type,
+            } => {
+                let next = if *from_end {
+                    next_element_sibling
+                } else {
+                    previous_element_sibling
+                };
+
+                let mut position = 1;
+                let mut sibling = next(element);
+                while let Some(current) = sibling {
+                    if !of_type || same_name(current, element) {
+                        position += 1;
+                    }
+                    sibling = next(current);
+                }
+
+                nth_matches(*a, *b, position)
+            }
+            Filter::Root => element
+                .parent
+                .get()
+                .is_some_and(|parent| matches!(parent.data, NodeData::Document)),
+            Filter::Empty => get_children(element).iter().all(|child| {
+                matches!(
+                    child.data,
+                    NodeData::Comment { .. } | NodeData::ProcessingInstruction { .. }
+                )
+            }),
+            Filter::Not(selectors) => !selectors.matches(element),
+            Filter::Is(selectors) => selectors.matches(element),
+            Filter::Has(selectors) => selectors.0.iter().any(|selector| {
+                // Only the elements after the anchor can match a relative selector.
+                let candidates = match selector.leading {
+                    Some(Combinator::NextSibling | Combinator::SubsequentSibling) => {
+                        let mut candidates = Vec::new();
+                        let mut sibling = next_element_sibling(element);
+                        while let Some(current) = sibling {
+                            candidates.push(current);
+                            candidates.extend(descendant_elements(current, false));
+                            sibling = next_element_sibling(current);
+                        }
+                        candidates
+                    }
+                    _ => descendant_elements(element, false),
+                };
+
+                candidates
+                    .into_iter()
+                    .any(|candidate| selector.matches(candidate, Some(element)))
+            }),
+        }
+    }
+}
+
+impl AttributeOperator {
+    fn matches(&self, value: &str, expected: &str, case_insensitive: bool) -> bool {
+        let (value, expected): (Cow<str>, Cow<str>) = if case_insensitive {
+            (
+                value.to_ascii_lowercase().into(),
+                expected.to_ascii_lowercase().into(),
+            )
+        } else {
+            (value.into(), expected.into())
+        };
+
+        match self {
+            AttributeOperator::Equals => value == expected,
+            AttributeOperator::Includes => value.split_ascii_whitespace().any(|v| v == expected),
+            AttributeOperator::DashMatch => {
+                value == expected
+                    || value
+                        .strip_prefix(&*expected)
+                        .is_some_and(|rest| rest.starts_with('-'))
+            }
+            AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(&*expected),
+            AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(&*expected),
+            AttributeOperator::Substring => !expected.is_empty() && value.contains(&*expected),
+        }
+    }
+}
+
+fn attribute_value<'a>(attrs: &'a [Attribute], name: &str, is_html: bool) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|attr| {
+            if is_html {
+                (*attr.name.local).eq_ignore_ascii_case(name)
+            } else {
+                &*attr.name.local == name
+            }
+        })
+        .map(|attr| &*attr.value)
+}
+
+/// Checks if there is a non-negative integer `n` for which `a * n + b` is the position.
+/// The arithmetic is done with `i128`, which cannot overflow for any `i64` values.
+fn nth_matches(a: i64, b: i64, position: i64) -> bool {
+    let (a, b, position) = (i128::from(a), i128::from(b), i128::from(position));
+
+    if a == 0 {
+        position == b
+    } else {
+        let difference = position - b;
+        difference % a == 0 && difference / a >= 0
+    }
+}
+
+fn same_name(node: &Node, other: &Node) -> bool {
+    match (&node.data, &other.data) {
+        (NodeData::Element { name, .. }, NodeData::Element { name: other, .. }) => name == other,
+        _ => false,
+    }
+}
+
+fn is_element(node: &Node) -> bool {
+    matches!(node.data, NodeData::Element { .. })
+}
+
+fn parent_element<'arena>(node: &Node<'arena>) -> Option<Ref<'arena>> {
+    node.parent.get().filter(|parent| is_element(parent))
+}
+
+fn previous_element_sibling<'arena>(node: &Node<'arena>) -> Option<Ref<'arena>> {
+    let mut sibling = node.previous_sibling.get();
+    while let Some(current) = sibling {
+        if is_element(current) {
+            return Some(current);
+        }
+        sibling = current.previous_sibling.get();
+    }
+
+    None
+}
+
+fn next_element_sibling<'arena>(node: &Node<'arena>) -> Option<Ref<'arena>> {
+    let mut sibling = node.next_sibling.get();
+    while let Some(current) = sibling {
+        if is_element(current) {
+            return Some(current);
+        }
+        sibling = current.next_sibling.get();
+    }
+
+    None
+}
+
+/// Returns the elements among the descendants of the node, in document order.
+pub(crate) fn descendant_elements<'arena>(
+    node: &Node<'arena>,
+    template_contents: bool,
+) -> Vec<Ref<'arena>> {
+    let mut elements = Vec::new();
+    let mut nodes: Vec<Ref<'arena>> = get_children(node).into_iter().rev().collect();
+
+    while let Some(node) = nodes.pop() {
+        if let NodeData::Element {
+            template_contents: contents,
+            ..
+        } = &node.data
+        {
+            elements.push(node);
+
+            // The contents come after the children, which templates usually don't have.
+            if let Some(contents) = contents.filter(|_| template_contents) {
+                nodes.extend(get_children(contents).into_iter().rev());
+            }
+
+            nodes.extend(get_children(node).into_iter().rev());
+        }
+    }
+
+    elements
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// The number of pseudo-classes with selectors being parsed.
+    nesting: usize,
+}
+
+impl Parser {
+    fn parse_list(&mut self, relative: bool) -> Result<SelectorList, Html5everExError> {
+        let mut selectors = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.parse_complex(relative)?);
+            self.skip_whitespace();
+
+            if !self.eat(',') {
+                return Ok(SelectorList(selectors));
+            }
+        }
+    }
+
+    fn parse_complex(&mut self, relative: bool) -> Result<ComplexSelector, Html5everExError> {
+        let leading = if relative {
+            let combinator = self.parse_combinator().unwrap_or(Combinator::Descendant);
+            self.skip_whitespace();
+            Some(combinator)
+        } else {
+            None
+        };
+
+        let mut compounds = vec![self.parse_compound()?];
+        let mut combinators = Vec::new();
+
+        loop {
+            let had_whitespace = self.skip_whitespace();
+
+            let combinator = match self.parse_combinator() {
+                Some(combinator) => {
+                    self.skip_whitespace();
+                    combinator
+                }
+                None if matches!(self.peek(), Some(',' | ')') | None) => break,
+                None if had_whitespace => Combinator::Descendant,
+                None => {
+                    return Err(self.error(format!("unexpected \"{}\"", self.chars[self.position])));
+                }
+            };
+
+            combinators.push(combinator);
+            compounds.push(self.parse_compound()?);
+        }
+
+        Ok(ComplexSelector {
+            leading,
+            compounds,
+            combinators,
+        })
+    }
+
+    fn parse_combinator(&mut self) -> Option<Combinator> {
+        let combinator = match self.peek()? {
+            '>' => Combinator::Child,
+            '+' => Combinator::NextSibling,
+            '~' => Combinator::SubsequentSibling,
+            _ => return None,
+        };
+
+        self.position += 1;
+        Some(combinator)
+    }
+
+    fn parse_compound(&mut self) -> Result<Compound, Html5everExError> {
+        let start = self.position;
+        let mut compound = Compound::default();
+
+        if !self.eat('*') && self.at_identifier() {
+            compound.name = Some(self.parse_identifier()?);
+        }
+
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.position += 1;
+                    compound.filters.push(Filter::Id(self.parse_identifier()?));
+                }
+                Some('.') => {
+                    self.position += 1;
+                    compound
+                        .filters
+                        .push(Filter::Class(self.parse_identifier()?));
+                }
+                Some('[') => compound.filters.push(self.parse_attribute()?),
+                Some(':') => self.parse_pseudo_class(&mut compound.filters)?,
+                _ => break,
+            }
+        }
+
+        if self.position == start {
+            return Err(self.error("expected a selector"));
+        }
+
+        Ok(compound)
+    }
+
+    fn parse_attribute(&mut self) -> Result<Filter, Html5everExError> {
+        self.position += 1;
+        self.skip_whitespace();
+
+        let name = self.parse_identifier()?;
+        self.skip_whitespace();
+
+        let operator = match (self.peek(), self.peek_at(1)) {
+            (Some(']'), _) => {
+                self.position += 1;
+                return Ok(Filter::Attribute {
+                    name,
+                    matcher: None,
+                    case_insensitive: false,
+                });
+            }
+            (Some('='), _) => AttributeOperator::Equals,
+            (Some('~'), Some('=')) => AttributeOperator::Includes,
+            (Some('|'), Some('=')) => AttributeOperator::DashMatch,
+            (Some('^'), Some('=')) => AttributeOperator::Prefix,
+            (Some('$'), Some('=')) => AttributeOperator::Suffix,
+            (Some('*'), Some('=')) => AttributeOperator::Substring,
+            _ => return Err(self.error("expected an attribute operator")),
+        };
+
+        self.position += if matches!(operator, AttributeOperator::Equals) {
+            1
+        } else {
+            2
+        };
+        self.skip_whitespace();
+
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => self.parse_string(quote)?,
+            _ => self.parse_identifier()?,
+        };
+        self.skip_whitespace();
+
+        // The flags for case-insensitive or case-sensitive matching.
+        let case_insensitive = match self.peek() {
+            Some('i' | 'I') => Some(true),
+            Some('s' | 'S') => Some(false),
+            _ => None,
+        };
+
+        if case_insensitive.is_some() {
+            self.position += 1;
+            self.skip_whitespace();
+        }
+
+        if !self.eat(']') {
+            return Err(self.error("expected \"]\""));
+        }
+
+        Ok(Filter::Attribute {
+            name,
+            matcher: Some((operator, value)),
+            case_insensitive: case_insensitive.unwrap_or(false),
+        })
+    }
+
+    fn parse_pseudo_class(&mut self, filters: &mut Vec<Filter>) -> Result<(), Html5everExError> {
+        self.position += 1;
+
+        if self.peek() == Some(':') {
+            return Err(self.error("pseudo-elements are not supported"));
+        }
+
+        let name = self.parse_identifier()?.to_ascii_lowercase();
+
+        if self.eat('(') {
+            self.skip_whitespace();
+
+            let filter = match name.as_str() {
+                "not" => Filter::Not(self.parse_nested_list(false)?),
+                "is" | "where" => Filter::Is(self.parse_nested_list(false)?),
+                "has" => Filter::Has(self.parse_nested_list(true)?),
+                "nth-child" => self.parse_nth(false, false)?,
+                "nth-last-child" => self.parse_nth(true, false)?,
+                "nth-of-type" => self.parse_nth(false, true)?,
+                "nth-last-of-type" => self.parse_nth(true, true)?,
+                _ => return Err(self.error(format!("unsupported pseudo-class \":{name}()\""))),
+            };
+
+            self.skip_whitespace();
+            if !self.eat(')') {
+                return Err(self.error("expected \")\""));
+            }
+
+            filters.push(filter);
+            return Ok(());
+        }
+
+        let first = |from_end, of_type| Filter::Nth {
+            a: 0,
+            b: 1,
+            from_end,
+            of_type,
+        };
+
+        match name.as_str() {
+            "first-child" => filters.push(first(false, false)),
+            "last-child" => filters.push(first(true, false)),
+            "only-child" => filters.extend([first(false, false), first(true, false)]),
+            "first-of-type" => filters.push(first(false, true)),
+            "last-of-type" => filters.push(first(true, true)),
+            "only-of-type" => filters.extend([first(false, true), first(true, true)]),
+            "root" => filters.push(Filter::Root),
+            "empty" => filters.push(Filter::Empty),
+            _ => return Err(self.error(format!("unsupported pseudo-class \":{name}\""))),
+        }
+
+        Ok(())
+    }
+
+    /// Parses the selectors of a pseudo-class like `:not()`.
+    fn parse_nested_list(&mut self, relative: bool) -> Result<SelectorList, Html5everExError> {
+        if self.nesting == MAX_NESTING {
+            return Err(self.error(format!(
+                "pseudo-classes nested more than {MAX_NESTING} times"
+            )));
+        }
+
+        self.nesting += 1;
+        let selectors = self.parse_list(relative)?;
+        self.nesting -= 1;
+
+        Ok(selectors)
+    }
+
+    /// Parses the `An+B` notation, like `2n+1`, `odd` or `3`.
+    fn parse_nth(&mut self, from_end: bool, of_type: bool) -> Result<Filter, Html5everExError> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c != ')') {
+            self.position += 1;
+        }
+
+        let argument: String = self.chars[start..self.position]
+            .iter()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        let (a, b) = match argument.as_str() {
+            "odd" => (2, 1),
+            "even" => (2, 0),
+            _ => parse_an_plus_b(&argument)
+                .ok_or_else(|| self.error(format!("invalid argument \"{argument}\"")))?,
+        };
+
+        Ok(Filter::Nth {
+            a,
+            b,
+            from_end,
+            of_type,
+        })
+    }
+
+    fn at_identifier(&self) -> bool {
+        match (self.peek(), self.peek_at(1)) {
+            (Some('-'), Some(c)) => is_name_start(c) || c == '-' || c == '\\',
+            (Some(c), _) => is_name_start(c) || c == '\\',
+            _ => false,
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, Html5everExError> {
+        if !self.at_identifier() {
+            return Err(self.error("expected an identifier"));
+        }
+
+        let mut identifier = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                identifier.push(self.parse_escape()?);
+            } else if is_name_start(c) || c.is_ascii_digit() || c == '-' {
+                identifier.push(c);
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+
+        Ok(identifier)
+    }
+
+    fn parse_string(&mut self, quote: char) -> Result<String, Html5everExError> {
+        self.position += 1;
+        let mut string = String::new();
+
+        loop {
+            match self.peek() {
+                Some(c) if c == quote => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                // An escaped newline continues the string in the next line.
+                Some('\\') if self.peek_at(1) == Some('\n') => self.position += 2,
+                Some('\\') => string.push(self.parse_escape()?),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => {
+                    string.push(c);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    /// Parses an escape sequence, which is either a backslash followed by
+    /// up to six hexadecimal digits, or by the escaped character.
+    fn parse_escape(&mut self) -> Result<char, Html5everExError> {
+        self.position += 1;
+
+        let digits: String = self.chars[self.position..]
+            .iter()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .take(6)
+            .collect();
+
+        if digits.is_empty() {
+            return match self.peek() {
+                Some('\n') | None => Err(self.error("invalid escape")),
+                Some(c) => {
+                    self.position += 1;
+                    Ok(c)
+                }
+            };
+        }
+
+        self.position += digits.len();
+
+        // A whitespace after the digits is part of the escape.
+        if self.peek().is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+
+        let code = u32::from_str_radix(&digits, 16).expect("the digits are hexadecimal");
+
+        Ok(char::from_u32(code)
+            .filter(|&c| c != '\0')
+            .unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.position;
+        while self.peek().is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+
+        self.position > start
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn error(&self, message: impl AsRef<str>) -> Html5everExError {
+        Html5everExError::InvalidSelector(format!(
+            "{} at position {}",
+            message.as_ref(),
+            self.position
+        ))
+    }
+}
+
+/// Parses the `An+B` notation without whitespace, except the keywords.
+fn parse_an_plus_b(argument: &str) -> Option<(i64, i64)> {
+    let Some((a, b)) = argument.split_once('n') else {
+        return Some((0, parse_integer(argument)?));
+    };
+
+    let a = match a {
+        "" | "+" => 1,
+        "-" => -1,
+        _ => parse_integer(a)?,
+    };
+
+    let b = match b {
+        "" => 0,
+        _ if b.starts_with(['+', '-']) => parse_integer(b)?,
+        _ => return None,
+    };
+
+    Some((a, b))
+}
+
+fn parse_integer(text: &str) -> Option<i64> {
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    text.parse().ok()
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C')
+}
@@ -0,0 +1,416 @@
+// Serialization of nodes back to HTML.
+//
+// The nodes are serialized by html5ever's serializer, which follows the
+// "serializing HTML fragments" algorithm from the spec. In order to use it,
+// the terms are first decoded into nodes in an arena, like the ones
+// created by the parser.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io;
+
+use html5ever::serialize::{Serialize, SerializeOpts, Serializer, TraversalScope, serialize};
+use html5ever::tendril::StrTendril;
+use html5ever::{Attribute, LocalName, Namespace, QualName, local_name, ns};
+use rustler::types::map::MapIterator;
+use rustler::types::tuple::get_tuple;
+use rustler::{Atom, Term, TermType};
+
+use crate::Html5everExError;
+use crate::arena_sink::{Arena, Node, NodeData, Ref, get_children};
+use crate::common::atoms;
+
+impl Serialize for Node<'_> {
+    fn serialize<S>(&self, serializer: &mut S, traversal_scope: TraversalScope) -> io::Result<()>
+    where
+        S: Serializer,
+    {
+        enum Op<'a, 'arena> {
+            Open(&'a Node<'arena>),
+            Close(&'a QualName),
+        }
+
+        let mut ops: Vec<Op> = match traversal_scope {
+            TraversalScope::IncludeNode => vec![Op::Open(self)],
+            TraversalScope::ChildrenOnly(_) => {
+                get_children(self).into_iter().rev().map(Op::Open).collect()
+            }
+        };
+
+        while let Some(op) = ops.pop() {
+            match op {
+                Op::Open(node) => match &node.data {
+                    NodeData::Document | NodeData::DocumentFragment => {
+                        ops.extend(get_children(node).into_iter().rev().map(Op::Open));
+                    }
+                    NodeData::Doctype { name, .. } => serializer.write_doctype(name)?,
+                    NodeData::Text { contents } => serializer.write_text(&contents.borrow())?,
+                    NodeData::Comment { contents } => serializer.write_comment(contents)?,
+                    NodeData::ProcessingInstruction { target, contents } => {
+                        serializer.write_processing_instruction(target, contents)?
+                    }
+                    NodeData::Element {
+                        name,
+                        attrs,
+                        template_contents,
+                        ..
+                    } => {
+                        let attrs = attrs.borrow();
+                        serializer.start_elem(
+                            name.clone(),
+                            attrs.iter().map(|attr| (&attr.name, &attr.value[..])),
+                        )?;
+
+                        ops.push(Op::Close(name));
+
+                        // The contents of templates are serialized as their children.
+                        let parent = template_contents.unwrap_or(node);
+                        ops.extend(get_children(parent).into_iter().rev().map(Op::Open));
+                    }
+                },
+                Op::Close(name) => serializer.end_elem(name.clone())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Serializes the node to HTML. Documents and fragments have only their
+/// children serialized.
+pub(crate) fn node_to_html(node: &Node) -> String {
+    let traversal_scope = match node.data {
+        NodeData::Document | NodeData::DocumentFragment => TraversalScope::ChildrenOnly(None),
+        _ => TraversalScope::IncludeNode,
+    };
+
+    let mut html: Vec<u8> = Vec::new();
+    let opts = SerializeOpts {
+        traversal_scope,
+        ..Default::default()
+    };
+
+    // Writing to a vector never fails.
+    serialize(&mut html, node, opts).expect("cannot serialize to a vector");
+
+    String::from_utf8(html).expect("serialized HTML is not valid UTF-8")
+}
+
+/// Decodes a tree in the nested format, as returned by `nodes_to_term`, into
+/// nodes in the arena. The tree can be a single node or a list of nodes,
+/// and the returned node is a document holding them.
+///
+/// Elements without a namespace are considered to be in the HTML namespace,
+/// unless they are inside an `<svg>` or a `<math>` element.
+pub(crate) fn nested_term_to_nodes<'arena>(
+    tree: Term,
+    arena: Arena<'arena>,
+) -> Result<Ref<'arena>, Html5everExError> {
+    let mut id = 0;
+    let document: Ref<'arena> = arena.alloc(Node::new(NodeData::Document, id, 0));
+
+    let mut terms: Vec<(Term, Ref<'arena>)> = if tree.get_type() == TermType::List {
+        let list: Vec<Term> = tree.decode().map_err(|_| invalid_node(tree))?;
+        list.into_iter()
+            .rev()
+            .map(|term| (term, document))
+            .collect()
+    } else {
+        vec![(tree, document)]
+    };
+
+    while let Some((term, parent)) = terms.pop() {
+        id += 1;
+
+        if term.get_type() == TermType::Binary {
+            let text: &str = term.decode().map_err(|_| invalid_node(term))?;
+            let data = NodeData::Text {
+                contents: RefCell::new(StrTendril::from(text)),
+            };
+            parent.append(arena.alloc(Node::new(data, id, 0)));
+            continue;
+        }
+
+        let elements = get_tuple(term).map_err(|_| invalid_node(term))?;
+
+        let data = match elements.as_slice() {
+            [tag, name, public_id, system_id, ..] if is_atom(*tag, atoms::doctype()) => {
+                NodeData::Doctype {
+                    name: decode_tendril(*name)?,
+                    public_id: decode_tendril(*public_id)?,
+                    system_id: decode_tendril(*system_id)?,
+                }
+            }
+            [tag, contents, ..] if is_atom(*tag, atoms::comment()) => NodeData::Comment {
+                contents: decode_tendril(*contents)?,
+            },
+            [tag, target, contents, ..] if is_atom(*tag, atoms::process_instruction()) => {
+                NodeData::ProcessingInstruction {
+                    target: decode_tendril(*target)?,
+                    contents: decode_tendril(*contents)?,
+                }
+            }
+            [name, attrs, children] | [name, attrs, children, _] => {
+                let name = decode_element_name(*name, parent)?;
+                let attrs = decode_attributes(*attrs)?;
+                let children: Vec<Term> = children.decode().map_err(|_| invalid_node(term))?;
+
+                let node = arena.alloc(Node::new(element_data(name, attrs), id, 0));
+                parent.append(node);
+                terms.extend(children.into_iter().rev().map(|child| (child, &*node)));
+                continue;
+            }
+            _ => return Err(invalid_node(term)),
+        };
+
+        parent.append(arena.alloc(Node::new(data, id, 0)));
+    }
+
+    Ok(document)
+}
+
+/// Decodes the nodes of a map in the flat format, as returned by
+/// `nodes_to_flat_term`, into nodes in the arena. Only the node with
+/// the given ID and its descendants are decoded, and the node is returned.
+///
+/// The namespaces of elements are taken from their `:namespace` keys,
+/// or inferred like in `nested_term_to_nodes` when they are missing.
+pub(crate) fn flat_term_to_nodes<'a, 'arena>(
+    nodes: Term<'a>,
+    id: Term<'a>,
+    arena: Arena<'arena>,
+) -> Result<Ref<'arena>, Html5everExError> {
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut root: Option<Ref<'arena>> = None;
+    let mut ids: Vec<(Term<'a>, Option<Ref<'arena>>)> = vec![(id, None)];
+
+    while let Some((id, parent)) = ids.pop() {
+        let (node_id, term) = get_flat_node(nodes, id, &mut visited)?;
+        let node_type: Atom = get_key(term, atoms::type_())?
+            .decode()
+            .map_err(|_| invalid_node(term))?;
+
+        let data = if node_type == atoms::document() {
+            NodeData::Document
+        } else if node_type == atoms::document_fragment() {
+            NodeData::DocumentFragment
+        } else if node_type == atoms::doctype() {
+            NodeData::Doctype {
+                name: decode_tendril(get_key(term, atoms::name())?)?,
+                public_id: decode_tendril(get_key(term, atoms::public_id())?)?,
+                system_id: decode_tendril(get_key(term, atoms::system_id())?)?,
+            }
+        } else if node_type == atoms::text() {
+            NodeData::Text {
+                contents: RefCell::new(decode_tendril(get_key(term, atoms::contents())?)?),
+            }
+        } else if node_type == atoms::comment() {
+            NodeData::Comment {
+                contents: decode_tendril(get_key(term, atoms::contents())?)?,
+            }
+        } else if node_type == atoms::process_instruction() {
+            NodeData::ProcessingInstruction {
+                target: decode_tendril(get_key(term, atoms::name())?)?,
+                contents: decode_tendril(get_key(term, atoms::contents())?)?,
+            }
+        } else if node_type == atoms::element() {
+            let local = LocalName::from(decode_str(get_key(term, atoms::name())?)?);
+            let name = match term.map_get(atoms::namespace()) {
+                Ok(namespace) => QualName::new(None, decode_namespace(namespace)?, local),
+                Err(_) => element_name(local, parent),
+            };
+            let attrs = decode_attributes(get_key(term, atoms::attrs())?)?;
+            let mut data = element_data(name, attrs);
+
+            // The contents of templates are in a separated document fragment.
+            if let Ok(content_id) = term.map_get(atoms::content()) {
+                let (content_node_id, content) = get_flat_node(nodes, content_id, &mut visited)?;
+                let fragment =
+                    arena.alloc(Node::new(NodeData::DocumentFragment, content_node_id, 0));
+                let children: Vec<Term> = get_key(content, atoms::children())?
+                    .decode()
+                    .map_err(|_| invalid_node(content))?;
+                ids.extend(
+                    children
+                        .into_iter()
+                        .rev()
+                        .map(|child| (child, Some(&*fragment))),
+                );
+
+                if let NodeData::Element {
+                    template_contents, ..
+                } = &mut data
+                {
+                    *template_contents = Some(fragment);
+                }
+            }
+
+            data
+        } else {
+            return Err(invalid_node(term));
+        };
+
+        let has_children = matches!(
+            data,
+            NodeData::Document | NodeData::DocumentFragment | NodeData::Element { .. }
+        );
+        let node: Ref<'arena> = arena.alloc(Node::new(data, node_id, 0));
+
+        if has_children {
+            let children: Vec<Term> = get_key(term, atoms::children())?
+                .decode()
+                .map_err(|_| invalid_node(term))?;
+            ids.extend(children.into_iter().rev().map(|child| (child, Some(node))));
+        }
+
+        match parent {
+            Some(parent) => parent.append(node),
+            None => root = Some(node),
+        }
+    }
+
+    Ok(root.expect("the first decoded node is the root"))
+}
+
+/// Returns the ID and the map of a node in the flat format. Each node can
+/// only be visited once, which protects against cycles in the children.
+fn get_flat_node<'a>(
+    nodes: Term<'a>,
+    id: Term<'a>,
+    visited: &mut HashSet<usize>,
+) -> Result<(usize, Term<'a>), Html5everExError> {
+    let node_id: usize = id.decode().map_err(|_| invalid_node(id))?;
+
+    if !visited.insert(node_id) {
+        return Err(Html5everExError::DuplicatedNode(node_id));
+    }
+
+    let node = nodes
+        .map_get(id)
+        .map_err(|_| Html5everExError::NodeNotFound(node_id))?;
+
+    Ok((node_id, node))
+}
+
+fn get_key(node: Term, key: Atom) -> Result<Term, Html5everExError> {
+    node.map_get(key).map_err(|_| invalid_node(node))
+}
+
+fn element_data<'arena>(name: QualName, attrs: Vec<Attribute>) -> NodeData<'arena> {
+    NodeData::Element {
+        name,
+        attrs: RefCell::new(attrs),
+        template_contents: None,
+        mathml_annotation_xml_integration_point: false,
+    }
+}
+
+/// The name can be a string or a `{namespace, name}` tuple.
+fn decode_element_name(term: Term, parent: &Node) -> Result<QualName, Html5everExError> {
+    if term.get_type() == TermType::Tuple {
+        let (namespace, local) = decode_expanded_name(term)?;
+        return Ok(QualName::new(None, namespace, local));
+    }
+
+    Ok(element_name(
+        LocalName::from(decode_str(term)?),
+        Some(parent),
+    ))
+}
+
+/// Without a namespace, the element inherits the namespace of its
+/// parent, following the rules of the parser for foreign content.
+fn element_name(local: LocalName, parent: Option<&Node>) -> QualName {
+    let namespace = match (&local, parent.map(|parent| &parent.data)) {
+        (&local_name!("svg"), _) => ns!(svg),
+        (&local_name!("math"), _) => ns!(mathml),
+        (_, Some(NodeData::Element { name, .. })) if is_html_integration_point(name) => ns!(html),
+        (_, Some(NodeData::Element { name, .. })) => name.ns.clone(),
+        _ => ns!(html),
+    };
+
+    QualName::new(None, namespace, local)
+}
+
+fn is_html_integration_point(name: &QualName) -> bool {
+    matches!(
+        (&name.ns, &name.local),
+        (&ns!(svg), &local_name!("foreignObject"))
+            | (&ns!(svg), &local_name!("desc"))
+            | (&ns!(svg), &local_name!("title"))
+            | (&ns!(mathml), &local_name!("annotation-xml"))
+            | (&ns!(mathml), &local_name!("mi"))
+            | (&ns!(mathml), &local_name!("mo"))
+            | (&ns!(mathml), &local_name!("mn"))
+            | (&ns!(mathml), &local_name!("ms"))
+            | (&ns!(mathml), &local_name!("mtext"))
+    )
+}
+
+/// Attributes are a list of `{name, value}` pairs, or a map.
+fn decode_attributes(term: Term) -> Result<Vec<Attribute>, Html5everExError> {
+    let pairs: Vec<(Term, Term)> = match MapIterator::new(term) {
+        Some(iterator) => iterator.collect(),
+        None => term.decode().map_err(|_| invalid_node(term))?,
+    };
+
+    pairs
+        .into_iter()
+        .map(|(name, value)| {
+            let name = if name.get_type() == TermType::Tuple {
+                let (namespace, local) = decode_expanded_name(name)?;
+                QualName::new(None, namespace, local)
+            } else {
+                QualName::new(None, ns!(), LocalName::from(decode_str(name)?))
+            };
+
+            Ok(Attribute {
+                name,
+                value: decode_tendril(value)?,
+            })
+        })
+        .collect()
+}
+
+/// Decodes a `{namespace, name}` tuple, as encoded by `ExpandedNameWrapper`.
+fn decode_expanded_name(term: Term) -> Result<(Namespace, LocalName), Html5everExError> {
+    let (namespace, local): (Term, Term) = term.decode().map_err(|_| invalid_node(term))?;
+
+    Ok((
+        decode_namespace(namespace)?,
+        LocalName::from(decode_str(local)?),
+    ))
+}
+
+/// Decodes a namespace, as encoded by `NamespaceWrapper`.
+fn decode_namespace(term: Term) -> Result<Namespace, Html5everExError> {
+    let namespace = match term.decode::<Atom>() {
+        Ok(atom) if atom == atoms::html() => ns!(html),
+        Ok(atom) if atom == atoms::svg() => ns!(svg),
+        Ok(atom) if atom == atoms::mathml() => ns!(mathml),
+        Ok(atom) if atom == atoms::xlink() => ns!(xlink),
+        Ok(atom) if atom == atoms::xml() => ns!(xml),
+        Ok(atom) if atom == atoms::xmlns() => ns!(xmlns),
+        Ok(atom) if atom == atoms::nil() => ns!(),
+        Ok(_) => return Err(invalid_node(term)),
+        Err(_) => Namespace::from(decode_str(term)?),
+    };
+
+    Ok(namespace)
+}
+
+fn decode_str<'a>(term: Term<'a>) -> Result<&'a str, Html5everExError> {
+    term.decode().map_err(|_| invalid_node(term))
+}
+
+fn decode_tendril(term: Term) -> Result<StrTendril, Html5everExError> {
+    decode_str(term).map(StrTendril::from)
+}
+
+fn is_atom(term: Term, atom: Atom) -> bool {
+    term.decode::<Atom>().is_ok_and(|decoded| decoded == atom)
+}
+
+fn invalid_node(term: Term) -> Html5everExError {
+    Html5everExError::InvalidNode(format!("{term:?}"))
+}
@@ -0,0 +1,207 @@
+// Parser sessions, which parse documents that arrive in chunks.
+//
+// The parser is kept in a resource between the chunks, along with the
+// arena it fills. Like in `resource.rs`, the resource owns the arena and
+// erases the lifetime of the nodes, which never leave the resource.
+//
+// The chunks are bytes, so a character can be split between two of them.
+// The bytes of an incomplete character are kept until the next chunk.
+
+use std::str::Utf8Error;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use encoding_rs::{Decoder, Encoding, UTF_8};
+use html5ever::driver::Parser;
+use html5ever::tendril::{StrTendril, TendrilSink};
+use rustler::{Env, Resource, ResourceArc, Term};
+
+use crate::arena_sink::{self, ArenaSink, Node};
+use crate::encoding::{self, PRESCAN_LIMIT};
+use crate::{Html5everExError, Limit, ParseOpts};
+
+pub(crate) struct ParserResource {
+    // The state is taken when the session is finished.
+    state: Mutex<Option<ParserState>>,
+}
+
+#[rustler::resource_impl]
+impl Resource for ParserResource {}
+
+pub(crate) struct ParserState {
+    // The parser must be dropped before the arena.
+    parser: Parser<ArenaSink<'static>>,
+    input: Input,
+    /// The number of bytes given to the parser so far.
+    input_bytes: usize,
+    opts: ParseOpts,
+    _arena: Box<typed_arena::Arena<Node<'static>>>,
+}
+
+// SAFETY: the parser and the nodes are not thread safe, because of the
+// cells and tendrils, but all of them are owned by the state, which is
+// only accessed by one thread at a time through the mutex.
+unsafe impl Send for ParserState {}
+
+/// How the chunks are decoded before being given to the parser.
+enum Input {
+    /// The chunks must be valid UTF-8. The bytes of a character
+    /// cut at the end of the last chunk are kept.
+    Utf8(Vec<u8>),
+    /// The chunks are kept until there are enough bytes to detect the encoding.
+    Sniffing(Vec<u8>),
+    /// The chunks are decoded with the detected encoding, or as UTF-8 with
+    /// replacement characters when the `lossy_utf8` option is given.
+    Decoding(Decoder, Option<&'static Encoding>),
+}
+
+impl ParserResource {
+    pub(crate) fn new(opts: ParseOpts) -> ResourceArc<ParserResource> {
+        // See the comment in `DocumentResource::parse` about the capacity.
+        let arena = Box::new(typed_arena::Arena::with_capacity(800));
+
+        // SAFETY: the arena is in the heap, so moving the box does not move
+        // the nodes, and the arena is dropped only with the state.
+        let arena_ref: &'static typed_arena::Arena<Node<'static>> =
+            unsafe { &*(&*arena as *const typed_arena::Arena<Node<'static>>) };
+
+        let input = if opts.detect_encoding || opts.transport_encoding.is_some() {
+            Input::Sniffing(Vec::new())
+        } else if opts.lossy_utf8 {
+            Input::Decoding(UTF_8.new_decoder_without_bom_handling(), None)
+        } else {
+            Input::Utf8(Vec::new())
+        };
+
+        ResourceArc::new(ParserResource {
+            state: Mutex::new(Some(ParserState {
+                parser: arena_sink::html5ever_parser(arena_ref, &opts),
+                input,
+                input_bytes: 0,
+                opts,
+                _arena: arena,
+            })),
+        })
+    }
+
+    /// Locks the state, which is `None` when the session is finished.
+    /// A panic while it was locked leaves the parser in an unknown
+    /// state, but it is still safe to drop it.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Option<ParserState>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl ParserState {
+    /// Parses the chunk. In case it is not valid UTF-8, or it makes the
+    /// input exceed the `max_input_bytes` option, nothing is parsed.
+    pub(crate) fn feed(&mut self, chunk: &[u8]) -> Result<(), Html5everExError> {
+        let input_bytes = self.input_bytes + chunk.len();
+        if let Some(max) = self.opts.max_input_bytes.filter(|&max| input_bytes > max) {
+            return Err(Html5everExError::LimitExceeded(Limit::MaxInputBytes, max));
+        }
+
+        match &mut self.input {
+            Input::Utf8(pending) => {
+                let length = pending.len();
+                pending.extend_from_slice(chunk);
+
+                let html = match std::str::from_utf8(pending) {
+                    Ok(html) => html,
+                    Err(error) if error.error_len().is_none() => {
+                        std::str::from_utf8(&pending[..error.valid_up_to()])?
+                    }
+                    Err(error) => {
+                        pending.truncate(length);
+                        return Err(utf8_error(error, self.input_bytes - length));
+                    }
+                };
+
+                let parsed = html.len();
+                self.parser.process(StrTendril::from_slice(html));
+                pending.drain(..parsed);
+            }
+            Input::Sniffing(buffer) => {
+                buffer.extend_from_slice(chunk);
+
+                if buffer.len() >= PRESCAN_LIMIT {
+                    let buffer = std::mem::take(buffer);
+                    let (encoding, bom_length) =
+                        encoding::sniff_prefix(&buffer, self.opts.transport_encoding.as_deref());
+
+                    self.input = Input::Decoding(
+                        encoding.new_decoder_without_bom_handling(),
+                        Some(encoding),
+                    );
+                    self.decode(&buffer[bom_length..], false);
+                }
+            }
+            Input::Decoding(..) => self.decode(chunk, false),
+        }
+
+        self.input_bytes = input_bytes;
+        arena_sink::check_parser_limits(&self.parser)
+    }
+
+    /// Parses the remaining input and returns the document in the nested
+    /// format, like the `parse` NIF. It is encoded here because the nodes
+    /// are dropped with the state.
+    pub(crate) fn finish<'a>(mut self, env: Env<'a>) -> Result<Term<'a>, Html5everExError> {
+        let encoding = match &mut self.input {
+            Input::Utf8(pending) => {
+                // The bytes of a character that was never completed.
+                let offset = self.input_bytes - pending.len();
+                std::str::from_utf8(pending).map_err(|error| utf8_error(error, offset))?;
+                None
+            }
+            Input::Sniffing(buffer) => {
+                let buffer = std::mem::take(buffer);
+                let (encoding, bom_length) =
+                    encoding::sniff(&buffer, self.opts.transport_encoding.as_deref());
+
+                self.input =
+                    Input::Decoding(encoding.new_decoder_without_bom_handling(), Some(encoding));
+                self.decode(&buffer[bom_length..], true);
+                Some(encoding)
+            }
+            Input::Decoding(_, encoding) => {
+                let encoding = *encoding;
+                self.decode(&[], true);
+                encoding
+            }
+        };
+
+        let parsed = self.parser.finish().check_limits()?;
+        let term = arena_sink::nodes_to_term(env, parsed.document, &self.opts);
+
+        crate::nested_result(env, term, &parsed, encoding, &self.opts)
+    }
+
+    /// Decodes the bytes, which are the last ones when `last` is `true`,
+    /// and parses the result.
+    fn decode(&mut self, bytes: &[u8], last: bool) {
+        let Input::Decoding(decoder, _) = &mut self.input else {
+            unreachable!("the decoder is created before decoding");
+        };
+
+        let capacity = decoder
+            .max_utf8_buffer_length(bytes.len())
+            .expect("the chunk is too big to be decoded");
+
+        // The string always has enough capacity for all the bytes.
+        let mut html = String::with_capacity(capacity);
+        let (_result, _read, _had_errors) = decoder.decode_to_string(bytes, &mut html, last);
+
+        if !html.is_empty() {
+            self.parser.process(StrTendril::from(html));
+        }
+    }
+}
+
+/// Returns the error of the pending bytes, which start at the `offset` of
+/// the input, so the position is counted from the start of the input.
+fn utf8_error(error: Utf8Error, offset: usize) -> Html5everExError {
+    Html5everExError::InvalidUtf8 {
+        valid_up_to: offset + error.valid_up_to(),
+        error_len: error.error_len(),
+    }
+}
@@ -0,0 +1,259 @@
+// Extraction of the visible text of nodes.
+//
+// This is a simplified version of the algorithm of the `innerText` getter:
+// https://html.spec.whatwg.org/multipage/dom.html#the-innertext-idl-attribute
+//
+// There is no CSS, so the elements are assumed to have the styles of the
+// user agent stylesheet of the spec. Whitespace is collapsed, except in
+// preformatted elements, and block-level elements are separated by the
+// "required line breaks" of the algorithm.
+
+use html5ever::{LocalName, local_name, ns};
+
+use crate::arena_sink::{Node, NodeData, get_children};
+
+/// Returns the visible text of the node.
+pub(crate) fn text_content(node: &Node) -> String {
+    enum Op<'a, 'arena> {
+        Open(&'a Node<'arena>),
+        Close(&'a LocalName, &'a Node<'arena>),
+    }
+
+    let mut text = Text::default();
+    let mut ops = vec![Op::Open(node)];
+    // The number of preformatted elements the current node is in.
+    let mut preformatted = 0;
+
+    while let Some(op) = ops.pop() {
+        match op {
+            Op::Open(node) => match &node.data {
+                NodeData::Document | NodeData::DocumentFragment => {
+                    ops.extend(get_children(node).into_iter().rev().map(Op::Open));
+                }
+                NodeData::Text { contents } if preformatted > 0 => {
+                    text.push_str(&contents.borrow());
+                }
+                NodeData::Text { contents } => text.push_collapsed(&contents.borrow()),
+                NodeData::Element { name, .. } if name.ns == ns!(html) => {
+                    let local = &name.local;
+
+                    if is_hidden(local) {
+                        continue;
+                    }
+
+                    if *local == local_name!("br") {
+                        text.push_str("\n");
+                        continue;
+                    }
+
+                    if is_preformatted(local) {
+                        preformatted += 1;
+                    }
+
+                    text.require_line_breaks(line_breaks(local));
+                    ops.push(Op::Close(local, node));
+                    ops.extend(get_children(node).into_iter().rev().map(Op::Open));
+                }
+                // Elements in other namespaces, like SVG, are inline.
+                NodeData::Element { .. } => {
+                    ops.extend(get_children(node).into_iter().rev().map(Op::Open));
+                }
+                NodeData::Doctype { .. }
+                | NodeData::Comment { .. }
+                | NodeData::ProcessingInstruction { .. } => {}
+            },
+            Op::Close(local, node) => {
+                if is_preformatted(local) {
+                    preformatted -= 1;
+                }
+
+                text.require_line_breaks(line_breaks(local));
+
+                // Cells are separated by tabs, except the last one of the row.
+                if is_cell(node) && has_next_cell(node) {
+                    text.push_str("\t");
+                }
+            }
+        }
+    }
+
+    text.contents
+}
+
+#[derive(Default)]
+struct Text {
+    contents: String,
+    /// The line breaks required before the next text, which are
+    /// dropped at the start and at the end of the contents.
+    line_breaks: usize,
+    /// Whether collapsed whitespace is pending before the next text.
+    space: bool,
+}
+
+impl Text {
+    fn push_str(&mut self, string: &str) {
+        if string.is_empty() {
+            return;
+        }
+
+        self.flush();
+        self.contents.push_str(string);
+    }
+
+    /// Pushes the text with runs of whitespace collapsed into a single space.
+    /// Whitespace at the start and the end of lines is removed.
+    fn push_collapsed(&mut self, string: &str) {
+        for (index, word) in string.split(is_whitespace).enumerate() {
+            // Every word but the first one comes after whitespace.
+            if index > 0 {
+                self.space = true;
+            }
+
+            if !word.is_empty() {
+                self.flush();
+                self.contents.push_str(word);
+            }
+        }
+    }
+
+    /// Whitespace around line breaks is removed as well.
+    fn require_line_breaks(&mut self, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        self.line_breaks = self.line_breaks.max(count);
+        self.space = false;
+    }
+
+    fn flush(&mut self) {
+        if self.line_breaks > 0 {
+            if !self.contents.is_empty() {
+                self.contents
+                    .extend(std::iter::repeat_n('\n', self.line_breaks));
+            }
+        } else if self.space && !self.contents.is_empty() && !self.contents.ends_with('\n') {
+            self.contents.push(' ');
+        }
+
+        self.line_breaks = 0;
+        self.space = false;
+    }
+}
+
+/// Elements that are not rendered, along with their descendants.
+fn is_hidden(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("head")
+            | local_name!("script")
+            | local_name!("style")
+            | local_name!("template")
+            | local_name!("noscript")
+            | local_name!("noframes")
+            | local_name!("datalist")
+            | local_name!("area")
+            | local_name!("base")
+            | local_name!("link")
+            | local_name!("meta")
+            | local_name!("title")
+            | local_name!("param")
+            | local_name!("source")
+            | local_name!("track")
+    )
+}
+
+fn is_preformatted(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("pre")
+            | local_name!("textarea")
+            | local_name!("listing")
+            | local_name!("plaintext")
+            | local_name!("xmp")
+    )
+}
+
+/// The required line breaks around the element: two for paragraphs,
+/// one for block-level elements, table rows and captions, and none
+/// for inline elements.
+fn line_breaks(local: &LocalName) -> usize {
+    match *local {
+        local_name!("p") => 2,
+        local_name!("address")
+        | local_name!("article")
+        | local_name!("aside")
+        | local_name!("blockquote")
+        | local_name!("body")
+        | local_name!("caption")
+        | local_name!("center")
+        | local_name!("dd")
+        | local_name!("details")
+        | local_name!("dialog")
+        | local_name!("dir")
+        | local_name!("div")
+        | local_name!("dl")
+        | local_name!("dt")
+        | local_name!("fieldset")
+        | local_name!("figcaption")
+        | local_name!("figure")
+        | local_name!("footer")
+        | local_name!("form")
+        | local_name!("h1")
+        | local_name!("h2")
+        | local_name!("h3")
+        | local_name!("h4")
+        | local_name!("h5")
+        | local_name!("h6")
+        | local_name!("header")
+        | local_name!("hgroup")
+        | local_name!("hr")
+        | local_name!("html")
+        | local_name!("legend")
+        | local_name!("li")
+        | local_name!("listing")
+        | local_name!("main")
+        | local_name!("menu")
+        | local_name!("nav")
+        | local_name!("ol")
+        | local_name!("optgroup")
+        | local_name!("option")
+        | local_name!("plaintext")
+        | local_name!("pre")
+        | local_name!("search")
+        | local_name!("section")
+        | local_name!("summary")
+        | local_name!("table")
+        | local_name!("tr")
+        | local_name!("ul")
+        | local_name!("xmp") => 1,
+        _ => 0,
+    }
+}
+
+fn is_cell(node: &Node) -> bool {
+    matches!(
+        &node.data,
+        NodeData::Element { name, .. }
+            if name.ns == ns!(html)
+                && matches!(name.local, local_name!("td") | local_name!("th"))
+    )
+}
+
+/// Whether a cell comes after the node in the same row.
+fn has_next_cell(node: &Node) -> bool {
+    let mut sibling = node.next_sibling.get();
+
+    while let Some(current) = sibling {
+        if is_cell(current) {
+            return true;
+        }
+        sibling = current.next_sibling.get();
+    }
+
+    false
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C')
+}
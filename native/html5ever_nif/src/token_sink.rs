@@ -0,0 +1,191 @@
+// A token sink that encodes the tokens of the tokenizer, without building
+// a tree. The tokens are the tags the author wrote, so there are no implied
+// elements, like `<tbody>`, and the end tags are kept even when misnested.
+//
+// The tokenizer does not know which elements have raw text, like `<script>`
+// and `<textarea>`, because that is decided by the tree builder. The sink
+// switches the state of the tokenizer after their start tags, like the tree
+// builder does for elements in HTML content. Inside `<svg>` and `<math>`
+// those elements have no raw text, and CDATA sections are allowed instead.
+
+use std::cell::{Cell, RefCell};
+
+use html5ever::local_name;
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::states::{Rawtext, Rcdata, ScriptData};
+use html5ever::tokenizer::{
+    BufferQueue, Doctype, Tag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer,
+};
+use rustler::{Encoder, Env, Term};
+
+use crate::ParseOpts;
+use crate::arena_sink::attributes_to_term;
+use crate::common::atoms;
+
+/// Returns the tokens of the HTML as a list, ending with `:eof`.
+pub(crate) fn tokenize<'a>(env: Env<'a>, html: &str, opts: &ParseOpts) -> Term<'a> {
+    let tokenizer = Tokenizer::new(TermSink::new(env, opts), Default::default());
+    let input = BufferQueue::default();
+    input.push_back(StrTendril::from_slice(html));
+
+    // The sink never blocks the tokenizer, so all the input is consumed.
+    let _ = tokenizer.feed(&input);
+    tokenizer.end();
+
+    tokenizer.sink.tokens.into_inner().encode(env)
+}
+
+struct TermSink<'a, 'opts> {
+    env: Env<'a>,
+    opts: &'opts ParseOpts,
+    tokens: RefCell<Vec<Term<'a>>>,
+    /// The characters of consecutive tokens, which are joined.
+    text: RefCell<String>,
+    foreign_content: ForeignContent,
+}
+
+impl<'a, 'opts> TermSink<'a, 'opts> {
+    fn new(env: Env<'a>, opts: &'opts ParseOpts) -> Self {
+        TermSink {
+            env,
+            opts,
+            tokens: RefCell::new(Vec::new()),
+            text: RefCell::new(String::new()),
+            foreign_content: ForeignContent::default(),
+        }
+    }
+
+    fn push(&self, token: Term<'a>) {
+        self.flush_text();
+        self.tokens.borrow_mut().push(token);
+    }
+
+    fn flush_text(&self) {
+        let mut text = self.text.borrow_mut();
+
+        if !text.is_empty() {
+            let token = (atoms::characters(), text.as_str()).encode(self.env);
+            self.tokens.borrow_mut().push(token);
+            text.clear();
+        }
+    }
+
+    fn tag_to_term(&self, tag: &Tag) -> Term<'a> {
+        let name: &str = &tag.name;
+
+        match tag.kind {
+            TagKind::StartTag => {
+                let attrs = attributes_to_term(self.env, &tag.attrs, self.opts);
+                (atoms::start_tag(), name, attrs, tag.self_closing).encode(self.env)
+            }
+            TagKind::EndTag => (atoms::end_tag(), name).encode(self.env),
+        }
+    }
+
+    fn doctype_to_term(&self, doctype: &Doctype) -> Term<'a> {
+        let (name, public_id, system_id) = (
+            doctype.name.as_deref(),
+            doctype.public_id.as_deref(),
+            doctype.system_id.as_deref(),
+        );
+
+        (
+            atoms::doctype(),
+            name,
+            public_id,
+            system_id,
+            doctype.force_quirks,
+        )
+            .encode(self.env)
+    }
+
+    fn parse_error_to_term(&self, message: &str, line: u64) -> Term<'a> {
+        let pairs = [
+            (atoms::line().encode(self.env), line.encode(self.env)),
+            (atoms::message().encode(self.env), message.encode(self.env)),
+        ];
+        let error = Term::map_from_pairs(self.env, &pairs).expect("the keys are unique");
+
+        (atoms::parse_error(), error).encode(self.env)
+    }
+}
+
+impl TokenSink for TermSink<'_, '_> {
+    type Handle = ();
+
+    fn process_token(&self, token: Token, line_number: u64) -> TokenSinkResult<()> {
+        match token {
+            Token::CharacterTokens(text) => self.text.borrow_mut().push_str(&text),
+            Token::NullCharacterToken => self.text.borrow_mut().push('\0'),
+            Token::TagToken(tag) => {
+                self.push(self.tag_to_term(&tag));
+                return self.foreign_content.process_tag(&tag);
+            }
+            Token::CommentToken(contents) => {
+                let contents: &str = &contents;
+                self.push((atoms::comment(), contents).encode(self.env));
+            }
+            Token::DoctypeToken(doctype) => self.push(self.doctype_to_term(&doctype)),
+            Token::EOFToken => self.push(atoms::eof().encode(self.env)),
+            Token::ParseError(message) if self.opts.parse_errors => {
+                self.push(self.parse_error_to_term(&message, line_number));
+            }
+            Token::ParseError(_) => {}
+        }
+
+        TokenSinkResult::Continue
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.foreign_content.is_active()
+    }
+}
+
+/// Tracks if the tags are in foreign content, counting the `<svg>` and
+/// `<math>` elements that are open. The elements that switch back to HTML
+/// inside them, like `<foreignObject>`, are not taken into account.
+#[derive(Default)]
+pub(crate) struct ForeignContent(Cell<usize>);
+
+impl ForeignContent {
+    pub(crate) fn is_active(&self) -> bool {
+        self.0.get() > 0
+    }
+
+    /// Updates the count with the tag, returning the state of the tokenizer
+    /// after it.
+    pub(crate) fn process_tag(&self, tag: &Tag) -> TokenSinkResult<()> {
+        let is_foreign_root = matches!(tag.name, local_name!("svg") | local_name!("math"));
+
+        match tag.kind {
+            TagKind::StartTag if is_foreign_root && !tag.self_closing => {
+                self.0.set(self.0.get() + 1);
+            }
+            TagKind::StartTag if !self.is_active() => return raw_text_state(tag),
+            TagKind::EndTag if is_foreign_root && self.is_active() => {
+                self.0.set(self.0.get() - 1);
+            }
+            _ => {}
+        }
+
+        TokenSinkResult::Continue
+    }
+}
+
+/// The state of the tokenizer after the start tag, which is not the data
+/// state for elements with raw text. Scripting is enabled, like in the
+/// parser, so the contents of `<noscript>` are raw text as well.
+fn raw_text_state(tag: &Tag) -> TokenSinkResult<()> {
+    match tag.name {
+        local_name!("title") | local_name!("textarea") => TokenSinkResult::RawData(Rcdata),
+        local_name!("style")
+        | local_name!("xmp")
+        | local_name!("iframe")
+        | local_name!("noembed")
+        | local_name!("noframes")
+        | local_name!("noscript") => TokenSinkResult::RawData(Rawtext),
+        local_name!("script") => TokenSinkResult::RawData(ScriptData),
+        local_name!("plaintext") => TokenSinkResult::Plaintext,
+        _ => TokenSinkResult::Continue,
+    }
+}
@@ -0,0 +1,1618 @@
+// XPath 1.0 expressions, evaluated over the nodes in the arena.
+//
+// See https://www.w3.org/TR/1999/REC-xpath-19991116/
+//
+// The nodes in the arena are mapped to the data model of XPath: the
+// attributes are represented by their element and index, because they
+// are not nodes in the arena, and the doctype is not part of the tree.
+// Names of HTML elements and attributes are matched case-insensitively,
+// like browsers do. Variables, namespace prefixes and the namespace axis
+// are not supported.
+
+use std::collections::HashMap;
+
+use html5ever::{Attribute, ns};
+use rustler::{Encoder, Env, Term};
+
+use crate::arena_sink::{self, Node, NodeData, Ref, get_children};
+use crate::common::{ExpandedNameWrapper, QualNameWrapper, StrTendrilWrapper, atoms};
+use crate::{Html5everExError, ParseOpts};
+
+/// The maximum nesting of the expressions in parentheses, predicates,
+/// function arguments and negations. They are parsed and evaluated
+/// recursively, on the small stack of the dirty schedulers. Each operator
+/// of a chain like `a + b + c` counts as a level as well, since it nests
+/// the operators before it.
+const MAX_NESTING: usize = 32;
+
+/// A parsed XPath expression.
+pub(crate) struct XPath(Expr);
+
+enum Expr {
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    Literal(String),
+    Number(f64),
+    Function(Function, Vec<Expr>),
+    /// A primary expression followed by predicates, like `(//p)[1]`.
+    Filter(Box<Expr>, Vec<Expr>),
+    Path(PathStart, Vec<Step>),
+}
+
+enum PathStart {
+    Root,
+    Context,
+    Expr(Box<Expr>),
+}
+
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Expr>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Axis {
+    Ancestor,
+    AncestorOrSelf,
+    Attribute,
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Following,
+    FollowingSibling,
+    Parent,
+    Preceding,
+    PrecedingSibling,
+    SelfNode,
+}
+
+enum NodeTest {
+    Name(String),
+    Any,
+    Node,
+    Text,
+    Comment,
+    ProcessingInstruction(Option<String>),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Plus,
+    Minus,
+    Multiply,
+    Div,
+    Mod,
+    Union,
+}
+
+#[derive(Clone, Copy)]
+enum Function {
+    Last,
+    Position,
+    Count,
+    Id,
+    LocalName,
+    NamespaceUri,
+    Name,
+    String,
+    Concat,
+    StartsWith,
+    Contains,
+    SubstringBefore,
+    SubstringAfter,
+    Substring,
+    StringLength,
+    NormalizeSpace,
+    Translate,
+    Boolean,
+    Not,
+    True,
+    False,
+    Lang,
+    Number,
+    Sum,
+    Floor,
+    Ceiling,
+    Round,
+}
+
+impl Function {
+    /// Returns the function with the minimum and maximum number of arguments.
+    fn from_name(name: &str) -> Option<(Function, usize, usize)> {
+        let function = match name {
+            "last" => (Function::Last, 0, 0),
+            "position" => (Function::Position, 0, 0),
+            "count" => (Function::Count, 1, 1),
+            "id" => (Function::Id, 1, 1),
+            "local-name" => (Function::LocalName, 0, 1),
+            "namespace-uri" => (Function::NamespaceUri, 0, 1),
+            "name" => (Function::Name, 0, 1),
+            "string" => (Function::String, 0, 1),
+            "concat" => (Function::Concat, 2, usize::MAX),
+            "starts-with" => (Function::StartsWith, 2, 2),
+            "contains" => (Function::Contains, 2, 2),
+            "substring-before" => (Function::SubstringBefore, 2, 2),
+            "substring-after" => (Function::SubstringAfter, 2, 2),
+            "substring" => (Function::Substring, 2, 3),
+            "string-length" => (Function::StringLength, 0, 1),
+            "normalize-space" => (Function::NormalizeSpace, 0, 1),
+            "translate" => (Function::Translate, 3, 3),
+            "boolean" => (Function::Boolean, 1, 1),
+            "not" => (Function::Not, 1, 1),
+            "true" => (Function::True, 0, 0),
+            "false" => (Function::False, 0, 0),
+            "lang" => (Function::Lang, 1, 1),
+            "number" => (Function::Number, 0, 1),
+            "sum" => (Function::Sum, 1, 1),
+            "floor" => (Function::Floor, 1, 1),
+            "ceiling" => (Function::Ceiling, 1, 1),
+            "round" => (Function::Round, 1, 1),
+            _ => return None,
+        };
+
+        Some(function)
+    }
+}
+
+/// The result of an expression.
+pub(crate) enum Value<'arena> {
+    /// The nodes are always in document order, without duplicates.
+    Nodes(Vec<XNode<'arena>>),
+    Boolean(bool),
+    Number(f64),
+    String(String),
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum XNode<'arena> {
+    Node(Ref<'arena>),
+    /// An attribute of an element, by its index.
+    Attribute(Ref<'arena>, usize),
+}
+
+impl XPath {
+    pub(crate) fn parse(expression: &str) -> Result<Self, Html5everExError> {
+        let (tokens, offsets) = tokenize(expression)?;
+        let mut parser = Parser {
+            tokens,
+            offsets,
+            position: 0,
+            length: expression.chars().count(),
+            nesting: 0,
+        };
+
+        let expr = parser.parse_expr()?;
+
+        if parser.position < parser.tokens.len() {
+            return Err(parser.error("unexpected token"));
+        }
+
+        Ok(XPath(expr))
+    }
+}
+
+/// Evaluates the expression with the root node as the context node.
+pub(crate) fn evaluate<'arena>(
+    xpath: &XPath,
+    root: Ref<'arena>,
+    template_contents: bool,
+) -> Result<Value<'arena>, Html5everExError> {
+    let evaluator = Evaluator::new(root, template_contents);
+    let context = Context {
+        node: XNode::Node(root),
+        position: 1,
+        size: 1,
+    };
+
+    evaluator.evaluate(&xpath.0, &context)
+}
+
+/// Encodes the value. Nodes are encoded like in `nodes_to_term`, and
+/// attributes as `{name, value}` tuples.
+pub(crate) fn value_to_term<'env>(env: Env<'env>, value: &Value, opts: &ParseOpts) -> Term<'env> {
+    match value {
+        Value::Nodes(nodes) => nodes
+            .iter()
+            .map(|node| match node {
+                XNode::Node(node) => arena_sink::nodes_to_term(env, node, opts),
+                XNode::Attribute(element, index) => with_attribute(element, *index, |attr| {
+                    (
+                        attribute_name_to_term(env, attr, opts),
+                        StrTendrilWrapper(&attr.value),
+                    )
+                        .encode(env)
+                }),
+            })
+            .collect::<Vec<Term>>()
+            .encode(env),
+        _ => scalar_to_term(env, value),
+    }
+}
+
+/// Encodes the value with the IDs of the nodes, and attributes
+/// as `{id, name}` tuples, with the ID of their element.
+pub(crate) fn value_to_ids_term<'env>(
+    env: Env<'env>,
+    value: &Value,
+    opts: &ParseOpts,
+) -> Term<'env> {
+    match value {
+        Value::Nodes(nodes) => nodes
+            .iter()
+            .map(|node| match node {
+                XNode::Node(node) => node.id.encode(env),
+                XNode::Attribute(element, index) => with_attribute(element, *index, |attr| {
+                    (element.id, attribute_name_to_term(env, attr, opts)).encode(env)
+                }),
+            })
+            .collect::<Vec<Term>>()
+            .encode(env),
+        _ => scalar_to_term(env, value),
+    }
+}
+
+fn attribute_name_to_term<'env>(env: Env<'env>, attr: &Attribute, opts: &ParseOpts) -> Term<'env> {
+    if opts.namespaces && attr.name.ns != ns!() {
+        ExpandedNameWrapper(&attr.name).encode(env)
+    } else {
+        QualNameWrapper(&attr.name).encode(env)
+    }
+}
+
+/// Numbers are floats, except for NaN and the infinities,
+/// which are encoded as atoms because the BEAM has no floats for them.
+fn scalar_to_term<'env>(env: Env<'env>, value: &Value) -> Term<'env> {
+    match value {
+        Value::Boolean(boolean) => boolean.encode(env),
+        Value::String(string) => string.encode(env),
+        Value::Number(number) if number.is_nan() => atoms::nan().encode(env),
+        Value::Number(number) if *number == f64::INFINITY => atoms::infinity().encode(env),
+        Value::Number(number) if *number == f64::NEG_INFINITY => atoms::neg_infinity().encode(env),
+        Value::Number(number) => number.encode(env),
+        Value::Nodes(_) => unreachable!("node-sets are not scalars"),
+    }
+}
+
+fn with_attribute<T>(element: &Node, index: usize, fun: impl FnOnce(&Attribute) -> T) -> T {
+    match &element.data {
+        NodeData::Element { attrs, .. } => fun(&attrs.borrow()[index]),
+        _ => unreachable!("only elements have attributes"),
+    }
+}
+
+struct Context<'arena> {
+    node: XNode<'arena>,
+    position: usize,
+    size: usize,
+}
+
+struct Evaluator<'arena> {
+    root: Ref<'arena>,
+    template_contents: bool,
+    /// The position of each node in document order, by ID.
+    order: HashMap<usize, usize>,
+    /// The templates of the document fragments with their contents, by ID.
+    templates: HashMap<usize, Ref<'arena>>,
+}
+
+impl<'arena> Evaluator<'arena> {
+    fn new(root: Ref<'arena>, template_contents: bool) -> Self {
+        let mut evaluator = Evaluator {
+            root,
+            template_contents,
+            order: HashMap::new(),
+            templates: HashMap::new(),
+        };
+
+        let mut nodes = vec![root];
+        while let Some(node) = nodes.pop() {
+            evaluator.order.insert(node.id, evaluator.order.len());
+
+            if let NodeData::Element {
+                template_contents: Some(contents),
+                ..
+            } = &node.data
+                && template_contents
+            {
+                evaluator.templates.insert(contents.id, node);
+            }
+
+            nodes.extend(evaluator.children(node).into_iter().rev());
+        }
+
+        evaluator
+    }
+
+    fn evaluate(
+        &self,
+        expr: &Expr,
+        context: &Context<'arena>,
+    ) -> Result<Value<'arena>, Html5everExError> {
+        let value = match expr {
+            Expr::Literal(string) => Value::String(string.clone()),
+            Expr::Number(number) => Value::Number(*number),
+            Expr::Negate(expr) => Value::Number(-self.number(&self.evaluate(expr, context)?)),
+            Expr::Binary(operator, left, right) => {
+                self.evaluate_binary(*operator, left, right, context)?
+            }
+            Expr::Function(function, arguments) => self.call(*function, arguments, context)?,
+            Expr::Filter(expr, predicates) => {
+                let nodes = self.evaluate_nodes(expr, context)?;
+                Value::Nodes(self.filter(nodes, predicates)?)
+            }
+            Expr::Path(start, steps) => {
+                let mut nodes = match start {
+                    PathStart::Root => vec![XNode::Node(self.root)],
+                    PathStart::Context => vec![context.node],
+                    PathStart::Expr(expr) => self.evaluate_nodes(expr, context)?,
+                };
+
+                for step in steps {
+                    nodes = self.step(step, &nodes)?;
+                }
+
+                Value::Nodes(nodes)
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn evaluate_nodes(
+        &self,
+        expr: &Expr,
+        context: &Context<'arena>,
+    ) -> Result<Vec<XNode<'arena>>, Html5everExError> {
+        match self.evaluate(expr, context)? {
+            Value::Nodes(nodes) => Ok(nodes),
+            _ => Err(Html5everExError::InvalidXPath(
+                "expected a node-set".to_string(),
+            )),
+        }
+    }
+
+    fn evaluate_binary(
+        &self,
+        operator: Operator,
+        left: &Expr,
+        right: &Expr,
+        context: &Context<'arena>,
+    ) -> Result<Value<'arena>, Html5everExError> {
+        let value = match operator {
+            Operator::Or => Value::Boolean(
+                self.boolean(&self.evaluate(left, context)?)
+                    || self.boolean(&self.evaluate(right, context)?),
+            ),
+            Operator::And => Value::Boolean(
+                self.boolean(&self.evaluate(left, context)?)
+                    && self.boolean(&self.evaluate(right, context)?),
+            ),
+            Operator::Union => {
+                let mut nodes = self.evaluate_nodes(left, context)?;
+                nodes.extend(self.evaluate_nodes(right, context)?);
+                self.sort(&mut nodes);
+                Value::Nodes(nodes)
+            }
+            Operator::Equal
+            | Operator::NotEqual
+            | Operator::Less
+            | Operator::LessEqual
+            | Operator::Greater
+            | Operator::GreaterEqual => {
+                let left = self.evaluate(left, context)?;
+                let right = self.evaluate(right, context)?;
+                Value::Boolean(self.compare(operator, &left, &right))
+            }
+            Operator::Plus
+            | Operator::Minus
+            | Operator::Multiply
+            | Operator::Div
+            | Operator::Mod => {
+                let left = self.number(&self.evaluate(left, context)?);
+                let right = self.number(&self.evaluate(right, context)?);
+
+                Value::Number(match operator {
+                    Operator::Plus => left + right,
+                    Operator::Minus => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Div => left / right,
+                    _ => left % right,
+                })
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Compares the values following the rules of XPath. When a node-set
+    /// is compared, the comparison is true if it is true for any node.
+    fn compare(&self, operator: Operator, left: &Value<'arena>, right: &Value<'arena>) -> bool {
+        match (left, right) {
+            (Value::Nodes(left), Value::Nodes(right)) => {
+                let right: Vec<Value> = right
+                    .iter()
+                    .map(|node| Value::String(self.string_value(*node)))
+                    .collect();
+
+                left.iter().any(|node| {
+                    let left = Value::String(self.string_value(*node));
+                    right
+                        .iter()
+                        .any(|right| self.compare_scalars(operator, &left, right))
+                })
+            }
+            (Value::Nodes(nodes), Value::Boolean(_)) => {
+                self.compare_scalars(operator, &Value::Boolean(!nodes.is_empty()), right)
+            }
+            (Value::Boolean(_), Value::Nodes(nodes)) => {
+                self.compare_scalars(operator, left, &Value::Boolean(!nodes.is_empty()))
+            }
+            (Value::Nodes(nodes), scalar) => nodes
+                .iter()
+                .any(|node| self.compare_scalars(operator, &self.node_as(*node, scalar), scalar)),
+            (scalar, Value::Nodes(nodes)) => nodes
+                .iter()
+                .any(|node| self.compare_scalars(operator, scalar, &self.node_as(*node, scalar))),
+            _ => self.compare_scalars(operator, left, right),
+        }
+    }
+
+    fn compare_scalars(
+        &self,
+        operator: Operator,
+        left: &Value<'arena>,
+        right: &Value<'arena>,
+    ) -> bool {
+        match operator {
+            Operator::Equal | Operator::NotEqual => {
+                let equal = match (left, right) {
+                    (Value::Boolean(_), _) | (_, Value::Boolean(_)) => {
+                        self.boolean(left) == self.boolean(right)
+                    }
+                    (Value::Number(_), _) | (_, Value::Number(_)) => {
+                        self.number(left) == self.number(right)
+                    }
+                    _ => self.string(left) == self.string(right),
+                };
+
+                equal == (operator == Operator::Equal)
+            }
+            _ => {
+                let (left, right) = (self.number(left), self.number(right));
+
+                match operator {
+                    Operator::Less => left < right,
+                    Operator::LessEqual => left <= right,
+                    Operator::Greater => left > right,
+                    _ => left >= right,
+                }
+            }
+        }
+    }
+
+    /// Converts the node to the type of the scalar it is compared to.
+    fn node_as(&self, node: XNode<'arena>, scalar: &Value) -> Value<'arena> {
+        let string = self.string_value(node);
+
+        match scalar {
+            Value::Number(_) => Value::Number(string_to_number(&string)),
+            _ => Value::String(string),
+        }
+    }
+
+    fn step(
+        &self,
+        step: &Step,
+        nodes: &[XNode<'arena>],
+    ) -> Result<Vec<XNode<'arena>>, Html5everExError> {
+        let mut result = Vec::new();
+
+        for node in nodes {
+            let candidates: Vec<XNode> = self
+                .axis(step.axis, *node)
+                .into_iter()
+                .filter(|candidate| self.matches_test(&step.test, *candidate))
+                .collect();
+
+            result.extend(self.filter(candidates, &step.predicates)?);
+        }
+
+        self.sort(&mut result);
+        Ok(result)
+    }
+
+    /// Filters the nodes by the predicates, with their positions in the given order.
+    fn filter(
+        &self,
+        mut nodes: Vec<XNode<'arena>>,
+        predicates: &[Expr],
+    ) -> Result<Vec<XNode<'arena>>, Html5everExError> {
+        for predicate in predicates {
+            let size = nodes.len();
+            let mut filtered = Vec::new();
+
+            for (index, node) in nodes.into_iter().enumerate() {
+                let context = Context {
+                    node,
+                    position: index + 1,
+                    size,
+                };
+
+                // A number is a shortcut for `position() = number`.
+                let keep = match self.evaluate(predicate, &context)? {
+                    Value::Number(number) => number == (index + 1) as f64,
+                    value => self.boolean(&value),
+                };
+
+                if keep {
+                    filtered.push(node);
+                }
+            }
+
+            nodes = filtered;
+        }
+
+        Ok(nodes)
+    }
+
+    /// Returns the nodes in the axis, in the order of the axis. That is
+    /// the reverse document order for the ancestor and preceding axes.
+    fn axis(&self, axis: Axis, node: XNode<'arena>) -> Vec<XNode<'arena>> {
+        let element = match node {
+            XNode::Node(node) => node,
+            XNode::Attribute(element, _) => {
+                return match axis {
+                    Axis::SelfNode | Axis::DescendantOrSelf => vec![node],
+                    Axis::Parent => vec![XNode::Node(element)],
+                    Axis::Ancestor | Axis::AncestorOrSelf => {
+                        let mut nodes = Vec::new();
+                        if axis == Axis::AncestorOrSelf {
+                            nodes.push(node);
+                        }
+                        nodes.push(XNode::Node(element));
+                        nodes.extend(self.axis(Axis::Ancestor, XNode::Node(element)));
+                        nodes
+                    }
+                    // The nodes following an attribute include the descendants of its element.
+                    Axis::Following => self
+                        .descendants(element)
+                        .into_iter()
+                        .map(XNode::Node)
+                        .chain(self.axis(Axis::Following, XNode::Node(element)))
+                        .collect(),
+                    Axis::Preceding => self.axis(Axis::Preceding, XNode::Node(element)),
+                    _ => Vec::new(),
+                };
+            }
+        };
+
+        let nodes: Vec<Ref> = match axis {
+            Axis::SelfNode => vec![element],
+            Axis::Child => self.children(element),
+            Axis::Descendant => self.descendants(element),
+            Axis::DescendantOrSelf => {
+                let mut nodes = vec![element];
+                nodes.extend(self.descendants(element));
+                nodes
+            }
+            Axis::Parent => self.parent(element).into_iter().collect(),
+            Axis::Ancestor | Axis::AncestorOrSelf => {
+                let mut nodes = Vec::new();
+                if axis == Axis::AncestorOrSelf {
+                    nodes.push(element);
+                }
+
+                let mut parent = self.parent(element);
+                while let Some(current) = parent {
+                    nodes.push(current);
+                    parent = self.parent(current);
+                }
+
+                nodes
+            }
+            Axis::FollowingSibling => siblings(element, |node| node.next_sibling.get()),
+            Axis::PrecedingSibling => siblings(element, |node| node.previous_sibling.get()),
+            Axis::Following => {
+                let mut nodes = Vec::new();
+                let mut current = Some(element);
+
+                while let Some(node) = current {
+                    for sibling in siblings(node, |node| node.next_sibling.get()) {
+                        nodes.push(sibling);
+                        nodes.extend(self.descendants(sibling));
+                    }
+                    current = self.parent(node);
+                }
+
+                nodes
+            }
+            Axis::Preceding => {
+                let mut nodes = Vec::new();
+                let mut current = Some(element);
+
+                while let Some(node) = current {
+                    for sibling in siblings(node, |node| node.previous_sibling.get()) {
+                        nodes.extend(self.descendants(sibling).into_iter().rev());
+                        nodes.push(sibling);
+                    }
+                    current = self.parent(node);
+                }
+
+                nodes
+            }
+            Axis::Attribute => {
+                return match &element.data {
+                    NodeData::Element { attrs, .. } => (0..attrs.borrow().len())
+                        .map(|index| XNode::Attribute(element, index))
+                        .collect(),
+                    _ => Vec::new(),
+                };
+            }
+        };
+
+        nodes.into_iter().map(XNode::Node).collect()
+    }
+
+    fn matches_test(&self, test: &NodeTest, node: XNode) -> bool {
+        match (test, node) {
+            (NodeTest::Node, _) => true,
+            (NodeTest::Any, XNode::Attribute(..)) => true,
+            (NodeTest::Name(name), XNode::Attribute(element, index)) => {
+                with_attribute(element, index, |attr| {
+                    names_match(&attr.name.local, name, is_html_element(element))
+                })
+            }
+            (_, XNode::Attribute(..)) => false,
+            (test, XNode::Node(node)) => match (test, &node.data) {
+                (NodeTest::Any, NodeData::Element { .. }) => true,
+                (NodeTest::Name(name), NodeData::Element { name: element, .. }) => {
+                    names_match(&element.local, name, element.ns == ns!(html))
+                }
+                (NodeTest::Text, NodeData::Text { .. }) => true,
+                (NodeTest::Comment, NodeData::Comment { .. }) => true,
+                (
+                    NodeTest::ProcessingInstruction(expected),
+                    NodeData::ProcessingInstruction { target, .. },
+                ) => expected
+                    .as_ref()
+                    .is_none_or(|expected| **expected == **target),
+                _ => false,
+            },
+        }
+    }
+
+    /// Returns the children of the node, which include the contents of
+    /// templates when they are requested. Doctypes are not part of the tree.
+    fn children(&self, node: &Node<'arena>) -> Vec<Ref<'arena>> {
+        let mut children = get_children(node);
+
+        if let NodeData::Element {
+            template_contents: Some(contents),
+            ..
+        } = &node.data
+            && self.template_contents
+        {
+            children.extend(get_children(contents));
+        }
+
+        children.retain(|child| !matches!(child.data, NodeData::Doctype { .. }));
+        children
+    }
+
+    /// Returns the descendants of the node in document order.
+    fn descendants(&self, node: &Node<'arena>) -> Vec<Ref<'arena>> {
+        let mut descendants = Vec::new();
+        let mut nodes: Vec<Ref> = self.children(node).into_iter().rev().collect();
+
+        while let Some(node) = nodes.pop() {
+            descendants.push(node);
+            nodes.extend(self.children(node).into_iter().rev());
+        }
+
+        descendants
+    }
+
+    /// The parent of the contents of a template is the template.
+    fn parent(&self, node: &Node<'arena>) -> Option<Ref<'arena>> {
+        let parent = node.parent.get()?;
+        Some(self.templates.get(&parent.id).copied().unwrap_or(parent))
+    }
+
+    fn sort(&self, nodes: &mut Vec<XNode<'arena>>) {
+        nodes.sort_by_key(|node| self.order_key(*node));
+        nodes.dedup_by_key(|node| self.order_key(*node));
+    }
+
+    /// The attributes of an element come after the element and before its children.
+    fn order_key(&self, node: XNode) -> (usize, usize) {
+        match node {
+            XNode::Node(node) => (self.order_position(node), 0),
+            XNode::Attribute(element, index) => (self.order_position(element), index + 1),
+        }
+    }
+
+    fn order_position(&self, node: &Node) -> usize {
+        self.order.get(&node.id).copied().unwrap_or(usize::MAX)
+    }
+
+    fn string_value(&self, node: XNode<'arena>) -> String {
+        let node = match node {
+            XNode::Node(node) => node,
+            XNode::Attribute(element, index) => {
+                return with_attribute(element, index, |attr| attr.value.to_string());
+            }
+        };
+
+        match &node.data {
+            NodeData::Text { contents } => contents.borrow().to_string(),
+            NodeData::Comment { contents } | NodeData::ProcessingInstruction { contents, .. } => {
+                contents.to_string()
+            }
+            NodeData::Doctype { .. } => String::new(),
+            NodeData::Document | NodeData::DocumentFragment | NodeData::Element { .. } => self
+                .descendants(node)
+                .into_iter()
+                .filter_map(|node| match &node.data {
+                    NodeData::Text { contents } => Some(contents.borrow().to_string()),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    fn string(&self, value: &Value<'arena>) -> String {
+        match value {
+            Value::Nodes(nodes) => nodes
+                .first()
+                .map(|node| self.string_value(*node))
+                .unwrap_or_default(),
+            Value::Boolean(boolean) => boolean.to_string(),
+            Value::Number(number) => number_to_string(*number),
+            Value::String(string) => string.clone(),
+        }
+    }
+
+    fn number(&self, value: &Value<'arena>) -> f64 {
+        match value {
+            Value::Number(number) => *number,
+            Value::Boolean(boolean) => f64::from(u8::from(*boolean)),
+            _ => string_to_number(&self.string(value)),
+        }
+    }
+
+    fn boolean(&self, value: &Value) -> bool {
+        match value {
+            Value::Nodes(nodes) => !nodes.is_empty(),
+            Value::Boolean(boolean) => *boolean,
+            Value::Number(number) => *number != 0.0 && !number.is_nan(),
+            Value::String(string) => !string.is_empty(),
+        }
+    }
+
+    fn call(
+        &self,
+        function: Function,
+        arguments: &[Expr],
+        context: &Context<'arena>,
+    ) -> Result<Value<'arena>, Html5everExError> {
+        let string_argument = |index: usize| -> Result<String, Html5everExError> {
+            match arguments.get(index) {
+                Some(argument) => Ok(self.string(&self.evaluate(argument, context)?)),
+                None => Ok(self.string_value(context.node)),
+            }
+        };
+        let number_argument = |index: usize| -> Result<f64, Html5everExError> {
+            Ok(self.number(&self.evaluate(&arguments[index], context)?))
+        };
+        // The first node of the argument, or the context node.
+        let node_argument = || -> Result<Option<XNode>, Html5everExError> {
+            match arguments.first() {
+                Some(argument) => Ok(self.evaluate_nodes(argument, context)?.first().copied()),
+                None => Ok(Some(context.node)),
+            }
+        };
+
+        let value = match function {
+            Function::Last => Value::Number(context.size as f64),
+            Function::Position => Value::Number(context.position as f64),
+            Function::Count => {
+                Value::Number(self.evaluate_nodes(&arguments[0], context)?.len() as f64)
+            }
+            Function::Id => {
+                let ids: Vec<String> = match self.evaluate(&arguments[0], context)? {
+                    Value::Nodes(nodes) => nodes
+                        .into_iter()
+                        .map(|node| self.string_value(node))
+                        .collect(),
+                    value => vec![self.string(&value)],
+                };
+                let ids: Vec<&str> = ids.iter().flat_map(|ids| split_whitespace(ids)).collect();
+
+                let elements = self
+                    .descendants(self.root)
+                    .into_iter()
+                    .filter(|node| {
+                        attribute_value(node, "id").is_some_and(|id| ids.contains(&id.as_str()))
+                    })
+                    .map(XNode::Node)
+                    .collect();
+
+                Value::Nodes(elements)
+            }
+            Function::LocalName | Function::Name | Function::NamespaceUri => {
+                let name = node_argument()?
+                    .map(|node| node_name(node, function))
+                    .unwrap_or_default();
+
+                Value::String(name)
+            }
+            Function::String => Value::String(string_argument(0)?),
+            Function::Concat => Value::String(
+                (0..arguments.len())
+                    .map(string_argument)
+                    .collect::<Result<String, _>>()?,
+            ),
+            Function::StartsWith => {
+                Value::Boolean(string_argument(0)?.starts_with(&string_argument(1)?))
+            }
+            Function::Contains => {
+                Value::Boolean(string_argument(0)?.contains(&string_argument(1)?))
+            }
+            Function::SubstringBefore => {
+                let string = string_argument(0)?;
+                let before = string
+                    .split_once(&string_argument(1)?)
+                    .map(|(before, _)| before.to_string());
+
+                Value::String(before.unwrap_or_default())
+            }
+            Function::SubstringAfter => {
+                let string = string_argument(0)?;
+                let after = string
+                    .split_once(&string_argument(1)?)
+                    .map(|(_, after)| after.to_string());
+
+                Value::String(after.unwrap_or_default())
+            }
+            Function::Substring => {
+                let string = string_argument(0)?;
+                let start = round(number_argument(1)?);
+                let end = match arguments.get(2) {
+                    Some(_) => start + round(number_argument(2)?),
+                    None => f64::INFINITY,
+                };
+
+                // The positions start at 1, and the comparisons are false for NaN.
+                let substring = string
+                    .chars()
+                    .enumerate()
+                    .filter(|(index, _)| {
+                        let position = (*index + 1) as f64;
+                        position >= start && position < end
+                    })
+                    .map(|(_, c)| c)
+                    .collect();
+
+                Value::String(substring)
+            }
+            Function::StringLength => Value::Number(string_argument(0)?.chars().count() as f64),
+            Function::NormalizeSpace => Value::String(
+                split_whitespace(&string_argument(0)?)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            Function::Translate => {
+                let from: Vec<char> = string_argument(1)?.chars().collect();
+                let to: Vec<char> = string_argument(2)?.chars().collect();
+
+                let translated = string_argument(0)?
+                    .chars()
+                    .filter_map(|c| match from.iter().position(|&from| from == c) {
+                        Some(index) => to.get(index).copied(),
+                        None => Some(c),
+                    })
+                    .collect();
+
+                Value::String(translated)
+            }
+            Function::Boolean => {
+                Value::Boolean(self.boolean(&self.evaluate(&arguments[0], context)?))
+            }
+            Function::Not => Value::Boolean(!self.boolean(&self.evaluate(&arguments[0], context)?)),
+            Function::True => Value::Boolean(true),
+            Function::False => Value::Boolean(false),
+            Function::Lang => {
+                let lang = string_argument(0)?.to_ascii_lowercase();
+                let mut node = match context.node {
+                    XNode::Node(node) | XNode::Attribute(node, _) => Some(node),
+                };
+
+                // The language is the one of the closest element with the attribute.
+                while let Some(current) = node {
+                    if let Some(value) = attribute_value(current, "lang") {
+                        let value = value.to_ascii_lowercase();
+                        return Ok(Value::Boolean(
+                            value == lang || value.starts_with(&format!("{lang}-")),
+                        ));
+                    }
+                    node = self.parent(current);
+                }
+
+                Value::Boolean(false)
+            }
+            Function::Number => match arguments.first() {
+                Some(argument) => Value::Number(self.number(&self.evaluate(argument, context)?)),
+                None => Value::Number(string_to_number(&self.string_value(context.node))),
+            },
+            Function::Sum => Value::Number(
+                self.evaluate_nodes(&arguments[0], context)?
+                    .into_iter()
+                    .map(|node| string_to_number(&self.string_value(node)))
+                    .sum(),
+            ),
+            Function::Floor => Value::Number(number_argument(0)?.floor()),
+            Function::Ceiling => Value::Number(number_argument(0)?.ceil()),
+            Function::Round => Value::Number(round(number_argument(0)?)),
+        };
+
+        Ok(value)
+    }
+}
+
+fn siblings<'arena>(
+    node: &Node<'arena>,
+    next: impl Fn(&Node<'arena>) -> Option<Ref<'arena>>,
+) -> Vec<Ref<'arena>> {
+    let mut siblings = Vec::new();
+    let mut sibling = next(node);
+
+    while let Some(current) = sibling {
+        if !matches!(current.data, NodeData::Doctype { .. }) {
+            siblings.push(current);
+        }
+        sibling = next(current);
+    }
+
+    siblings
+}
+
+fn is_html_element(node: &Node) -> bool {
+    matches!(&node.data, NodeData::Element { name, .. } if name.ns == ns!(html))
+}
+
+fn names_match(name: &str, expected: &str, is_html: bool) -> bool {
+    if is_html {
+        name.eq_ignore_ascii_case(expected)
+    } else {
+        name == expected
+    }
+}
+
+fn attribute_value(node: &Node, name: &str) -> Option<String> {
+    match &node.data {
+        NodeData::Element { attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|attr| &*attr.name.local == name)
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
+
+fn node_name(node: XNode, function: Function) -> String {
+    match node {
+        XNode::Attribute(element, index) => with_attribute(element, index, |attr| match function {
+            Function::NamespaceUri => attr.name.ns.to_string(),
+            Function::Name => match &attr.name.prefix {
+                Some(prefix) => format!("{}:{}", prefix, attr.name.local),
+                None => attr.name.local.to_string(),
+            },
+            _ => attr.name.local.to_string(),
+        }),
+        XNode::Node(node) => match (&node.data, function) {
+            (NodeData::Element { name, .. }, Function::NamespaceUri) => name.ns.to_string(),
+            (NodeData::Element { name, .. }, _) => name.local.to_string(),
+            (
+                NodeData::ProcessingInstruction { target, .. },
+                Function::LocalName | Function::Name,
+            ) => target.to_string(),
+            _ => String::new(),
+        },
+    }
+}
+
+fn round(number: f64) -> f64 {
+    if number.is_finite() {
+        (number + 0.5).floor()
+    } else {
+        number
+    }
+}
+
+fn number_to_string(number: f64) -> String {
+    if number.is_nan() {
+        "NaN".to_string()
+    } else if number == f64::INFINITY {
+        "Infinity".to_string()
+    } else if number == f64::NEG_INFINITY {
+        "-Infinity".to_string()
+    } else if number == 0.0 {
+        // Negative zero is "0" as well.
+        "0".to_string()
+    } else {
+        number.to_string()
+    }
+}
+
+/// Converts the string following the `Number` production of XPath, which
+/// has no exponents or signs other than the minus. Otherwise it is NaN.
+fn string_to_number(string: &str) -> f64 {
+    let trimmed = string.trim_matches(is_whitespace);
+    let digits = trimmed.strip_prefix('-').unwrap_or(trimmed);
+
+    let valid = digits.bytes().any(|byte| byte.is_ascii_digit())
+        && digits
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || byte == b'.')
+        && digits.bytes().filter(|&byte| byte == b'.').count() <= 1;
+
+    if valid {
+        trimmed.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
+fn split_whitespace(string: &str) -> impl Iterator<Item = &str> {
+    string.split(is_whitespace).filter(|part| !part.is_empty())
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Slash,
+    DoubleSlash,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Dot,
+    DoubleDot,
+    At,
+    Comma,
+    DoubleColon,
+    Operator(Operator),
+    NameTest(String),
+    NodeType(String),
+    FunctionName(String),
+    AxisName(String),
+    Literal(String),
+    Number(f64),
+}
+
+/// Splits the expression into tokens, following the rules of XPath to tell
+/// apart names and operators. The character offsets of the tokens are
+/// returned as well.
+fn tokenize(expression: &str) -> Result<(Vec<Token>, Vec<usize>), Html5everExError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut offsets = Vec::new();
+    let mut position = 0;
+
+    let error = |message: &str, position: usize| {
+        Html5everExError::InvalidXPath(format!("{message} at position {position}"))
+    };
+
+    while position < chars.len() {
+        let c = chars[position];
+        let next = chars.get(position + 1).copied();
+
+        if is_whitespace(c) {
+            position += 1;
+            continue;
+        }
+
+        // A name or a "*" is an operator when it follows something that
+        // can be an operand, like a name test or a closing parenthesis.
+        let after_operand = !matches!(
+            tokens.last(),
+            None | Some(
+                Token::At
+                    | Token::DoubleColon
+                    | Token::LeftParen
+                    | Token::LeftBracket
+                    | Token::Comma
+                    | Token::Operator(_)
+                    | Token::Slash
+                    | Token::DoubleSlash
+            )
+        );
+
+        let start = position;
+        let token = match (c, next) {
+            ('/', Some('/')) => Token::DoubleSlash,
+            ('/', _) => Token::Slash,
+            ('(', _) => Token::LeftParen,
+            (')', _) => Token::RightParen,
+            ('[', _) => Token::LeftBracket,
+            (']', _) => Token::RightBracket,
+            ('.', Some('.')) => Token::DoubleDot,
+            ('.', next) if !next.is_some_and(|c| c.is_ascii_digit()) => Token::Dot,
+            ('@', _) => Token::At,
+            (',', _) => Token::Comma,
+            (':', Some(':')) => Token::DoubleColon,
+            ('|', _) => Token::Operator(Operator::Union),
+            ('+', _) => Token::Operator(Operator::Plus),
+            ('-', _) => Token::Operator(Operator::Minus),
+            ('=', _) => Token::Operator(Operator::Equal),
+            ('!', Some('=')) => Token::Operator(Operator::NotEqual),
+            ('<', Some('=')) => Token::Operator(Operator::LessEqual),
+            ('<', _) => Token::Operator(Operator::Less),
+            ('>', Some('=')) => Token::Operator(Operator::GreaterEqual),
+            ('>', _) => Token::Operator(Operator::Greater),
+            ('*', _) if after_operand => Token::Operator(Operator::Multiply),
+            ('*', _) => Token::NameTest("*".to_string()),
+            ('$', _) => return Err(error("variables are not supported", position)),
+            (quote @ ('"' | '\''), _) => {
+                let length = chars[position + 1..]
+                    .iter()
+                    .position(|&c| c == quote)
+                    .ok_or_else(|| error("unterminated string", position))?;
+
+                let literal = chars[position + 1..position + 1 + length].iter().collect();
+                position += length + 2;
+                tokens.push(Token::Literal(literal));
+                offsets.push(start);
+                continue;
+            }
+            (c, _) if c.is_ascii_digit() || c == '.' => {
+                let length = chars[position..]
+                    .iter()
+                    .position(|c| !c.is_ascii_digit() && *c != '.')
+                    .unwrap_or(chars.len() - position);
+
+                let number: String = chars[position..position + length].iter().collect();
+                let number = number
+                    .parse()
+                    .map_err(|_| error("invalid number", position))?;
+
+                position += length;
+                tokens.push(Token::Number(number));
+                offsets.push(start);
+                continue;
+            }
+            (c, _) if is_name_start(c) => {
+                let length = chars[position..]
+                    .iter()
+                    .position(|&c| !is_name_char(c))
+                    .unwrap_or(chars.len() - position);
+
+                let name: String = chars[position..position + length].iter().collect();
+                position += length;
+
+                let token = if after_operand {
+                    match name.as_str() {
+                        "and" => Token::Operator(Operator::And),
+                        "or" => Token::Operator(Operator::Or),
+                        "mod" => Token::Operator(Operator::Mod),
+                        "div" => Token::Operator(Operator::Div),
+                        _ => return Err(error("expected an operator", start)),
+                    }
+                } else if chars.get(position) == Some(&':') && chars.get(position + 1) != Some(&':')
+                {
+                    return Err(error("namespace prefixes are not supported", position));
+                } else {
+                    let rest = &chars[position..];
+                    let next = rest.iter().position(|&c| !is_whitespace(c));
+
+                    match next.map(|index| &rest[index..]) {
+                        Some(['(', ..]) => match name.as_str() {
+                            "node" | "text" | "comment" | "processing-instruction" => {
+                                Token::NodeType(name)
+                            }
+                            _ => Token::FunctionName(name),
+                        },
+                        Some([':', ':', ..]) => Token::AxisName(name),
+                        _ => Token::NameTest(name),
+                    }
+                };
+
+                tokens.push(token);
+                offsets.push(start);
+                continue;
+            }
+            _ => return Err(error("unexpected character", position)),
+        };
+
+        position += match token {
+            Token::DoubleSlash
+            | Token::DoubleDot
+            | Token::DoubleColon
+            | Token::Operator(Operator::NotEqual | Operator::LessEqual | Operator::GreaterEqual) => {
+                2
+            }
+            _ => 1,
+        };
+
+        tokens.push(token);
+        offsets.push(start);
+    }
+
+    Ok((tokens, offsets))
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start(c) || c.is_ascii_digit() || c == '-' || c == '.'
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    offsets: Vec<usize>,
+    position: usize,
+    /// The length of the expression, for errors at its end.
+    length: usize,
+    /// The number of nested expressions being parsed.
+    nesting: usize,
+}
+
+impl Parser {
+    fn parse_expr(&mut self) -> Result<Expr, Html5everExError> {
+        self.parse_binary(0)
+    }
+
+    /// Parses an expression nested in another one, like an argument.
+    fn parse_nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, Html5everExError>,
+    ) -> Result<T, Html5everExError> {
+        self.nest()?;
+        let result = parse(self)?;
+        self.nesting -= 1;
+
+        Ok(result)
+    }
+
+    /// Adds a level of nesting, which the caller removes when it is done.
+    fn nest(&mut self) -> Result<(), Html5everExError> {
+        if self.nesting == MAX_NESTING {
+            return Err(self.error(&format!("expressions nested more than {MAX_NESTING} times")));
+        }
+
+        self.nesting += 1;
+        Ok(())
+    }
+
+    /// Parses the binary operators with a precedence of at least `level`,
+    /// from the lowest one. The operands are parsed by precedence climbing,
+    /// so the nested expressions don't go through a call for each level.
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, Html5everExError> {
+        const LEVELS: &[&[Operator]] = &[
+            &[Operator::Or],
+            &[Operator::And],
+            &[Operator::Equal, Operator::NotEqual],
+            &[
+                Operator::Less,
+                Operator::LessEqual,
+                Operator::Greater,
+                Operator::GreaterEqual,
+            ],
+            &[Operator::Plus, Operator::Minus],
+            &[Operator::Multiply, Operator::Div, Operator::Mod],
+        ];
+
+        let nesting = self.nesting;
+        let mut left = self.parse_unary()?;
+
+        while let Some(Token::Operator(operator)) = self.peek() {
+            let operator = *operator;
+            let Some(operator_level) = LEVELS
+                .iter()
+                .position(|operators| operators.contains(&operator))
+                .filter(|&operator_level| operator_level >= level)
+            else {
+                break;
+            };
+
+            self.position += 1;
+            self.nest()?;
+            let right = self.parse_binary(operator_level + 1)?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        self.nesting = nesting;
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Html5everExError> {
+        if self.eat(&Token::Operator(Operator::Minus)) {
+            return Ok(Expr::Negate(Box::new(
+                self.parse_nested(Self::parse_unary)?,
+            )));
+        }
+
+        let nesting = self.nesting;
+        let mut left = self.parse_path()?;
+
+        while self.eat(&Token::Operator(Operator::Union)) {
+            self.nest()?;
+            let right = self.parse_path()?;
+            left = Expr::Binary(Operator::Union, Box::new(left), Box::new(right));
+        }
+
+        self.nesting = nesting;
+        Ok(left)
+    }
+
+    fn parse_path(&mut self) -> Result<Expr, Html5everExError> {
+        if self.eat(&Token::Slash) {
+            let steps = if self.at_step() {
+                self.parse_relative_path()?
+            } else {
+                Vec::new()
+            };
+
+            return Ok(Expr::Path(PathStart::Root, steps));
+        }
+
+        if self.eat(&Token::DoubleSlash) {
+            let mut steps = vec![descendant_or_self()];
+            steps.extend(self.parse_relative_path()?);
+            return Ok(Expr::Path(PathStart::Root, steps));
+        }
+
+        if self.at_step() {
+            return Ok(Expr::Path(PathStart::Context, self.parse_relative_path()?));
+        }
+
+        let primary = self.parse_primary()?;
+        let predicates = self.parse_predicates()?;
+
+        let filter = if predicates.is_empty() {
+            primary
+        } else {
+            Expr::Filter(Box::new(primary), predicates)
+        };
+
+        let mut steps = Vec::new();
+        if self.eat(&Token::Slash) {
+            steps.extend(self.parse_relative_path()?);
+        } else if self.eat(&Token::DoubleSlash) {
+            steps.push(descendant_or_self());
+            steps.extend(self.parse_relative_path()?);
+        } else {
+            return Ok(filter);
+        }
+
+        Ok(Expr::Path(PathStart::Expr(Box::new(filter)), steps))
+    }
+
+    fn at_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(
+                Token::NameTest(_)
+                    | Token::NodeType(_)
+                    | Token::AxisName(_)
+                    | Token::At
+                    | Token::Dot
+                    | Token::DoubleDot
+            )
+        )
+    }
+
+    fn parse_relative_path(&mut self) -> Result<Vec<Step>, Html5everExError> {
+        let mut steps = vec![self.parse_step()?];
+
+        loop {
+            if self.eat(&Token::Slash) {
+                steps.push(self.parse_step()?);
+            } else if self.eat(&Token::DoubleSlash) {
+                steps.push(descendant_or_self());
+                steps.push(self.parse_step()?);
+            } else {
+                return Ok(steps);
+            }
+        }
+    }
+
+    fn parse_step(&mut self) -> Result<Step, Html5everExError> {
+        let axis = match self.peek() {
+            Some(Token::Dot) | Some(Token::DoubleDot) => {
+                let axis = if self.eat(&Token::Dot) {
+                    Axis::SelfNode
+                } else {
+                    self.position += 1;
+                    Axis::Parent
+                };
+
+                return Ok(Step {
+                    axis,
+                    test: NodeTest::Node,
+                    predicates: Vec::new(),
+                });
+            }
+            Some(Token::At) => {
+                self.position += 1;
+                Axis::Attribute
+            }
+            Some(Token::AxisName(name)) => {
+                let axis = match name.as_str() {
+                    "ancestor" => Axis::Ancestor,
+                    "ancestor-or-self" => Axis::AncestorOrSelf,
+                    "attribute" => Axis::Attribute,
+                    "child" => Axis::Child,
+                    "descendant" => Axis::Descendant,
+                    "descendant-or-self" => Axis::DescendantOrSelf,
+                    "following" => Axis::Following,
+                    "following-sibling" => Axis::FollowingSibling,
+                    "parent" => Axis::Parent,
+                    "preceding" => Axis::Preceding,
+                    "preceding-sibling" => Axis::PrecedingSibling,
+                    "self" => Axis::SelfNode,
+                    _ => return Err(self.error(&format!("unsupported axis \"{name}\""))),
+                };
+
+                self.position += 1;
+                self.expect(&Token::DoubleColon)?;
+                axis
+            }
+            _ => Axis::Child,
+        };
+
+        let test = self.parse_node_test()?;
+        let predicates = self.parse_predicates()?;
+
+        Ok(Step {
+            axis,
+            test,
+            predicates,
+        })
+    }
+
+    fn parse_node_test(&mut self) -> Result<NodeTest, Html5everExError> {
+        let test = match self.peek() {
+            Some(Token::NameTest(name)) if name == "*" => NodeTest::Any,
+            Some(Token::NameTest(name)) => NodeTest::Name(name.clone()),
+            Some(Token::NodeType(node_type)) => {
+                let node_type = node_type.clone();
+                self.position += 1;
+                self.expect(&Token::LeftParen)?;
+
+                let test = match node_type.as_str() {
+                    "node" => NodeTest::Node,
+                    "text" => NodeTest::Text,
+                    "comment" => NodeTest::Comment,
+                    _ => match self.peek() {
+                        Some(Token::Literal(target)) => {
+                            let target = target.clone();
+                            self.position += 1;
+                            NodeTest::ProcessingInstruction(Some(target))
+                        }
+                        _ => NodeTest::ProcessingInstruction(None),
+                    },
+                };
+
+                self.expect(&Token::RightParen)?;
+                return Ok(test);
+            }
+            _ => return Err(self.error("expected a node test")),
+        };
+
+        self.position += 1;
+        Ok(test)
+    }
+
+    fn parse_predicates(&mut self) -> Result<Vec<Expr>, Html5everExError> {
+        let mut predicates = Vec::new();
+
+        while self.eat(&Token::LeftBracket) {
+            predicates.push(self.parse_nested(Self::parse_expr)?);
+            self.expect(&Token::RightBracket)?;
+        }
+
+        Ok(predicates)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, Html5everExError> {
+        let expr = match self.peek() {
+            Some(Token::LeftParen) => {
+                self.position += 1;
+                let expr = self.parse_nested(Self::parse_expr)?;
+                self.expect(&Token::RightParen)?;
+                return Ok(expr);
+            }
+            Some(Token::Literal(literal)) => Expr::Literal(literal.clone()),
+            Some(Token::Number(number)) => Expr::Number(*number),
+            Some(Token::FunctionName(name)) => {
+                let name = name.clone();
+                let Some((function, min, max)) = Function::from_name(&name) else {
+                    return Err(self.error(&format!("unknown function \"{name}\"")));
+                };
+                let start = self.position;
+
+                self.position += 1;
+                self.expect(&Token::LeftParen)?;
+
+                let mut arguments = Vec::new();
+                if !self.eat(&Token::RightParen) {
+                    loop {
+                        arguments.push(self.parse_nested(Self::parse_expr)?);
+
+                        if !self.eat(&Token::Comma) {
+                            break;
+                        }
+                    }
+                    self.expect(&Token::RightParen)?;
+                }
+
+                if arguments.len() < min || arguments.len() > max {
+                    self.position = start;
+                    return Err(self.error(&format!("wrong number of arguments for \"{name}\"")));
+                }
+
+                return Ok(Expr::Function(function, arguments));
+            }
+            _ => return Err(self.error("expected an expression")),
+        };
+
+        self.position += 1;
+        Ok(expr)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), Html5everExError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error("unexpected token"))
+        }
+    }
+
+    fn error(&self, message: &str) -> Html5everExError {
+        match self.offsets.get(self.position) {
+            Some(offset) => {
+                Html5everExError::InvalidXPath(format!("{message} at position {offset}"))
+            }
+            None => {
+                Html5everExError::InvalidXPath(format!("{message} at position {}", self.length))
+            }
+        }
+    }
+}
+
+/// The step that `//` is short for.
+fn descendant_or_self() -> Step {
+    Step {
+        axis: Axis::DescendantOrSelf,
+        test: NodeTest::Node,
+        predicates: Vec::new(),
+    }
+}
@@ -1,6 +1,7 @@
 defmodule Html5everTest do
   use ExUnit.Case, async: true
   doctest Html5ever
+  doctest Html5ever.Error
 
   def read_html(name) do
     path = Path.join([:code.priv_dir(:html5ever), "test_data", name])
@@ -26,8 +27,11 @@ defmodule Html5everTest do
       <<98, 29, 104, 122, 46, 145, 14, 37, 122, 155, 227, 121, 49, 120, 108, 209, 155, 113, 229,
         98, 90, 181, 146>>
 
-    assert Html5ever.parse(invalid) ==
-             {:error, "cannot transform bytes from binary to a valid UTF8 string"}
+    assert {:error, %Html5ever.Error{reason: {:invalid_utf8, 5, 1}} = error} =
+             Html5ever.parse(invalid)
+
+    assert Exception.message(error) ==
+             "cannot transform bytes from binary to a valid UTF8 string at position 5"
   end
 
   test "flat parse basic html" do
@@ -37,7 +41,7 @@ defmodule Html5everTest do
       {:ok,
        %{
          nodes: %{
-           0 => %{children: [1], id: 0, parent: nil, type: :document},
+           0 => %{children: [1], id: 0, parent: nil, type: :document, quirks_mode: :quirks},
            1 => %{children: [2, 3], id: 1, parent: 0, type: :element, attrs: [], name: "html"},
            2 => %{children: [], id: 2, parent: 1, type: :element, attrs: [], name: "head"},
            3 => %{
@@ -60,8 +64,8 @@ defmodule Html5everTest do
       <<98, 29, 104, 122, 46, 145, 14, 37, 122, 155, 227, 121, 49, 120, 108, 209, 155, 113, 229,
         98, 90, 181, 146>>
 
-    assert Html5ever.flat_parse(invalid) ==
-             {:error, "cannot transform bytes from binary to a valid UTF8 string"}
+    assert {:error, %Html5ever.Error{reason: {:invalid_utf8, 5, 1}}} =
+             Html5ever.flat_parse(invalid)
   end
 
   test "flat parse basic html with attributes as maps" do
@@ -72,7 +76,7 @@ defmodule Html5everTest do
       {:ok,
        %{
          nodes: %{
-           0 => %{children: [1], id: 0, parent: nil, type: :document},
+           0 => %{children: [1], id: 0, parent: nil, type: :document, quirks_mode: :quirks},
            1 => %{children: [2, 3], id: 1, parent: 0, type: :element, attrs: %{}, name: "html"},
            2 => %{children: [], id: 2, parent: 1, type: :element, attrs: %{}, name: "head"},
            3 => %{
@@ -120,6 +124,22 @@ defmodule Html5everTest do
     assert {:ok, _} = Html5ever.flat_parse(html)
   end
 
+  test "extremely deep html" do
+    html = String.duplicate("<span>", 100_000)
+
+    assert {:ok, [{"html", [], [{"head", [], []}, {"body", [], [span]}]}]} =
+             Html5ever.parse(html)
+
+    depth =
+      Stream.unfold(span, fn
+        {"span", [], children} -> {1, List.first(children)}
+        nil -> nil
+      end)
+      |> Enum.count()
+
+    assert depth == 100_000
+  end
+
   test "reasonably deep html" do
     html = """
     <!doctype html>
@@ -319,4 +339,1170 @@ defmodule Html5everTest do
                 }
               ]}
   end
+
+  test "parse fragment keeps table rows with a tbody context" do
+    html = "<tr><td>1</td><td>2</td></tr><tr><td>3</td></tr>"
+
+    assert Html5ever.parse_fragment(html, "tbody") ==
+             {:ok,
+              [
+                {"tr", [], [{"td", [], ["1"]}, {"td", [], ["2"]}]},
+                {"tr", [], [{"td", [], ["3"]}]}
+              ]}
+
+    assert Html5ever.parse_fragment(html, "div") == {:ok, ["123"]}
+  end
+
+  test "parse fragment with attributes as maps" do
+    html = "<a href=\"/home\" class=\"link\">Home</a> and text"
+
+    assert Html5ever.parse_fragment(html, "p", attributes_as_maps: true) ==
+             {:ok, [{"a", %{"href" => "/home", "class" => "link"}, ["Home"]}, " and text"]}
+  end
+
+  test "parse fragment with a svg context" do
+    html = "<title>Circle</title><circle r=\"2\"/>"
+
+    assert Html5ever.parse_fragment(html, "svg") ==
+             {:ok, [{"title", [], ["Circle"]}, {"circle", [{"r", "2"}], []}]}
+  end
+
+  test "flat parse fragment" do
+    html = "<li>One<li>Two"
+
+    assert {:ok, %{root: root, nodes: nodes}} = Html5ever.flat_parse_fragment(html, "ul")
+
+    assert %{type: :document, parent: nil, children: [first, second]} = nodes[root]
+    assert %{name: "li", parent: ^root, children: [first_text]} = nodes[first]
+    assert %{name: "li", parent: ^root, children: [second_text]} = nodes[second]
+    assert %{type: :text, contents: "One"} = nodes[first_text]
+    assert %{type: :text, contents: "Two"} = nodes[second_text]
+    assert map_size(nodes) == 5
+  end
+
+  test "parse with parse errors" do
+    html = "<!doctype html>\n<p>Hello\n</div>"
+
+    assert Html5ever.parse(html, parse_errors: true) ==
+             {:ok,
+              [
+                {:doctype, "html", "", ""},
+                {"html", [], [{"head", [], []}, {"body", [], [{"p", [], ["Hello\n"]}]}]}
+              ], %{errors: [%{line: 3, message: "Unexpected token"}]}}
+
+    assert {:ok, _tree, %{errors: []}} =
+             Html5ever.parse("<!doctype html><p>Hello</p>", parse_errors: true)
+  end
+
+  test "flat parse with parse errors" do
+    html = "<p id=\"a\" id=\"b\">Hi</p>"
+
+    assert {:ok, %{errors: errors, nodes: _, root: 0}} =
+             Html5ever.flat_parse(html, parse_errors: true)
+
+    assert errors == [
+             %{line: 1, message: "Duplicate attribute"},
+             %{line: 1, message: "Unexpected token"}
+           ]
+  end
+
+  test "parse with unknown options" do
+    assert_raise ArgumentError, fn -> Html5ever.parse("<p>Hi</p>", unknown: true) end
+  end
+
+  test "parse with quirks mode" do
+    assert {:ok, _, %{quirks_mode: :no_quirks}} =
+             Html5ever.parse("<!doctype html><p>Hi</p>", quirks_mode: true)
+
+    assert {:ok, _, %{quirks_mode: :quirks}} = Html5ever.parse("<p>Hi</p>", quirks_mode: true)
+
+    html = """
+    <!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN"
+      "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">
+    <p>Hi</p>
+    """
+
+    assert {:ok, _, %{quirks_mode: :limited_quirks}} = Html5ever.parse(html, quirks_mode: true)
+  end
+
+  test "flat parse includes the quirks mode in the document node" do
+    html = """
+    <!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">
+    <p>Hi</p>
+    """
+
+    assert {:ok, %{root: root, nodes: nodes}} = Html5ever.flat_parse(html)
+    assert %{type: :document, quirks_mode: :quirks} = nodes[root]
+  end
+
+  test "parse with source positions" do
+    html = """
+    <!doctype html>
+    <html>
+    <body>
+    <!-- comment -->
+    <p class="intro"
+       id="first">Hello</p>
+    </body>
+    </html>
+    """
+
+    assert Html5ever.parse(html, source_positions: true) ==
+             {:ok,
+              [
+                {:doctype, "html", "", "", %{line: 1}},
+                {"html", [],
+                 [
+                   {"head", [], [], %{line: 3}},
+                   {"body", [],
+                    [
+                      "\n",
+                      {:comment, " comment ", %{line: 4}},
+                      "\n",
+                      {"p", [{"class", "intro"}, {"id", "first"}], ["Hello"], %{line: 6}},
+                      "\n\n\n"
+                    ], %{line: 3}}
+                 ], %{line: 2}}
+              ]}
+  end
+
+  test "flat parse with source positions" do
+    html = "<!doctype html>\n<p>Hello</p>\n\n<p>World</p>"
+
+    assert {:ok, %{root: root, nodes: nodes}} = Html5ever.flat_parse(html, source_positions: true)
+
+    refute Map.has_key?(nodes[root], :line)
+
+    lines =
+      for {_id, %{type: :element, name: "p", line: line}} <- nodes, do: line
+
+    assert Enum.sort(lines) == [2, 4]
+  end
+
+  test "parse with encoding detected from a meta charset" do
+    # "日本" encoded in Shift_JIS.
+    html = <<"<!doctype html><meta charset=\"shift_jis\"><p>", 0x93, 0xFA, 0x96, 0x7B, "</p>">>
+
+    assert Html5ever.parse(html, detect_encoding: true) ==
+             {:ok,
+              [
+                {:doctype, "html", "", ""},
+                {"html", [],
+                 [
+                   {"head", [], [{"meta", [{"charset", "shift_jis"}], []}]},
+                   {"body", [], [{"p", [], ["日本"]}]}
+                 ]}
+              ], %{encoding: "Shift_JIS"}}
+  end
+
+  test "parse with encoding detected from a meta content" do
+    # "а" encoded in KOI8-R.
+    detect = fn meta ->
+      {:ok, _, %{encoding: encoding}} =
+        Html5ever.parse(<<meta::binary, "<p>", 0xC1, "</p>">>, detect_encoding: true)
+
+      encoding
+    end
+
+    content = ~s(content="text/html; charset=koi8-r")
+
+    assert detect.(~s(<meta #{content} http-equiv=content-type>)) == "KOI8-R"
+    assert detect.(~s(<meta #{content}>)) == "windows-1252"
+
+    # The "charset" attribute always wins, while the "content" is only
+    # used when there is no valid "charset" before it.
+    assert detect.(~s(<meta #{content} http-equiv=content-type charset=utf-8>)) == "UTF-8"
+    assert detect.(~s(<meta #{content} http-equiv=content-type charset=bogus>)) == "windows-1252"
+    assert detect.(~s(<meta charset=utf-8 http-equiv=content-type #{content}>)) == "UTF-8"
+    assert detect.(~s(<meta charset=bogus http-equiv=content-type #{content}>)) == "KOI8-R"
+  end
+
+  test "parse with encoding from the transport layer" do
+    # "中文" encoded in GB18030.
+    html = <<"<meta charset=\"utf-8\"><p>", 0xD6, 0xD0, 0xCE, 0xC4, "</p>">>
+
+    assert {:ok, tree, %{encoding: "gb18030"}} =
+             Html5ever.parse(html, transport_encoding: "GB18030")
+
+    assert [{"html", [], [_head, {"body", [], [{"p", [], ["中文"]}]}]}] = tree
+  end
+
+  test "parse with encoding detection falls back to windows-1252" do
+    html = <<"<p>caf", 0xE9, "</p>">>
+
+    assert {:ok, tree, %{encoding: "windows-1252"}} = Html5ever.parse(html, detect_encoding: true)
+    assert [{"html", [], [_head, {"body", [], [{"p", [], ["café"]}]}]}] = tree
+
+    assert {:ok, _, %{encoding: "UTF-8"}} = Html5ever.parse("<p>café</p>", detect_encoding: true)
+  end
+
+  test "flat parse with encoding detected from the byte order mark" do
+    html = <<0xEF, 0xBB, 0xBF, "<meta charset=\"iso-8859-1\"><p>olá</p>">>
+
+    assert {:ok, %{encoding: "UTF-8", nodes: nodes}} =
+             Html5ever.flat_parse(html, detect_encoding: true)
+
+    assert Enum.any?(nodes, fn {_id, node} -> node[:contents] == "olá" end)
+  end
+
+  test "parse with lossy UTF8" do
+    html = <<"<p>Hello", 0xFF, " world", 0xC3, "</p>">>
+
+    assert {:ok, [{"html", [], [_head, {"body", [], [{"p", [], [text]}]}]}]} =
+             Html5ever.parse(html, lossy_utf8: true)
+
+    assert text == "Hello\uFFFD world\uFFFD"
+  end
+
+  test "flat parse with lossy UTF8" do
+    html = <<"<p>caf", 0xE9, "</p>">>
+
+    assert {:ok, %{nodes: nodes}} = Html5ever.flat_parse(html, lossy_utf8: true)
+    assert Enum.any?(nodes, fn {_id, node} -> node[:contents] == "caf\uFFFD" end)
+  end
+
+  test "parse with namespaces" do
+    html = """
+    <title>Doc</title>
+    <svg viewBox="0 0 10 10"><title>Icon</title><use xlink:href="#icon"/></svg>
+    <math><mi>x</mi></math>
+    """
+
+    assert {:ok, [{{:html, "html"}, [], [head, body]}]} = Html5ever.parse(html, namespaces: true)
+
+    assert {{:html, "head"}, [], [{{:html, "title"}, [], ["Doc"]}, "\n"]} = head
+
+    assert {{:html, "body"}, [],
+            [
+              {{:svg, "svg"}, [{"viewBox", "0 0 10 10"}],
+               [
+                 {{:svg, "title"}, [], ["Icon"]},
+                 {{:svg, "use"}, [{{:xlink, "href"}, "#icon"}], []}
+               ]},
+              "\n",
+              {{:mathml, "math"}, [], [{{:mathml, "mi"}, [], ["x"]}]},
+              "\n"
+            ]} = body
+  end
+
+  test "flat parse with namespaces" do
+    html = "<svg><a xlink:href=\"/home\" href=\"/other\"></a></svg>"
+
+    assert {:ok, %{nodes: nodes}} =
+             Html5ever.flat_parse(html, namespaces: true, attributes_as_maps: true)
+
+    elements = for {_id, %{type: :element} = node} <- nodes, into: %{}, do: {node.name, node}
+
+    assert %{namespace: :html} = elements["body"]
+    assert %{namespace: :svg} = elements["svg"]
+
+    assert %{namespace: :svg, attrs: %{{:xlink, "href"} => "/home", "href" => "/other"}} =
+             elements["a"]
+  end
+
+  test "parse html with a template tag and template contents" do
+    html = """
+    <template id="flower">
+      <h2>Flower</h2>
+      <img src="img_white_flower.jpg">
+    </template>
+    """
+
+    assert Html5ever.parse(html, template_contents: true) ==
+             {:ok,
+              [
+                {"html", [],
+                 [
+                   {"head", [],
+                    [
+                      {"template", [{"id", "flower"}],
+                       [
+                         "\n  ",
+                         {"h2", [], ["Flower"]},
+                         "\n  ",
+                         {"img", [{"src", "img_white_flower.jpg"}], []},
+                         "\n"
+                       ]},
+                      "\n"
+                    ]},
+                   {"body", [], []}
+                 ]}
+              ]}
+  end
+
+  test "flat parse html with a template tag and template contents" do
+    html = "<body><template><p>Hello</p></template></body>"
+
+    assert {:ok, %{nodes: nodes}} = Html5ever.flat_parse(html, template_contents: true)
+
+    assert [template] = for {_id, %{name: "template"} = node} <- nodes, do: node
+    assert %{children: [], content: content} = template
+
+    assert %{type: :document_fragment, parent: nil, children: [p]} = nodes[content]
+    assert %{name: "p", parent: ^content, children: [text]} = nodes[p]
+    assert %{contents: "Hello"} = nodes[text]
+  end
+
+  test "flat parse html with a legacy doctype" do
+    html = """
+    <!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd">
+    <html><body></body></html>
+    """
+
+    assert {:ok, %{nodes: nodes}} = Html5ever.flat_parse(html)
+
+    assert [doctype] = for {_id, %{type: :doctype} = node} <- nodes, do: node
+
+    assert doctype == %{
+             id: 1,
+             parent: 0,
+             type: :doctype,
+             name: "html",
+             public_id: "-//W3C//DTD XHTML 1.0 Strict//EN",
+             system_id: "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd"
+           }
+  end
+
+  test "serialize a parsed document" do
+    html =
+      ~s(<!DOCTYPE html><html><head><title>Fish &amp; Chips</title>) <>
+        ~s(<script>if (a < b) { go("&amp;") }</script></head>) <>
+        ~s(<body><p class="x" title='"quoted"'>Hi<br><img src="a.png"></p><!-- note --></body></html>)
+
+    assert {:ok, tree} = Html5ever.parse(html)
+
+    assert Html5ever.serialize(tree) ==
+             {:ok,
+              ~s(<!DOCTYPE html><html><head><title>Fish &amp; Chips</title>) <>
+                ~s(<script>if (a < b) { go("&amp;") }</script></head>) <>
+                ~s(<body><p class="x" title="&quot;quoted&quot;">Hi<br><img src="a.png"></p>) <>
+                ~s(<!-- note --></body></html>)}
+  end
+
+  test "serialize a tree with attributes as maps and source positions" do
+    assert {:ok, tree} =
+             Html5ever.parse("<div id=main><span>a < b</span></div>",
+               attributes_as_maps: true,
+               source_positions: true
+             )
+
+    assert Html5ever.serialize(tree) ==
+             {:ok,
+              ~s(<html><head></head><body><div id="main"><span>a &lt; b</span></div></body></html>)}
+  end
+
+  test "serialize svg elements" do
+    tree = {"svg", [{"viewBox", "0 0 10 10"}], [{"path", [{"d", "M0 0"}], []}]}
+
+    assert Html5ever.serialize(tree) ==
+             {:ok, ~s(<svg viewBox="0 0 10 10"><path d="M0 0"></path></svg>)}
+  end
+
+  test "serialize a tree with namespaces" do
+    html = ~s(<svg><a xlink:href="#top">Top</a></svg>)
+
+    assert {:ok, tree} = Html5ever.parse_fragment(html, "body", namespaces: true)
+
+    assert Html5ever.serialize(tree) == {:ok, html}
+  end
+
+  test "serialize an invalid tree" do
+    assert {:error, %Html5ever.Error{reason: {:invalid_node, _}, message: message}} =
+             Html5ever.serialize([{"p", [], [:oops]}])
+
+    assert "invalid node: " <> _ = message
+  end
+
+  test "flat serialize a parsed document" do
+    html =
+      ~s(<!DOCTYPE html><html><head></head><body><p id="a">1 &lt; 2</p><!-- c --></body></html>)
+
+    assert {:ok, flat} = Html5ever.flat_parse(html)
+
+    assert Html5ever.flat_serialize(flat) == {:ok, html}
+  end
+
+  test "flat serialize an edited document starting from a node" do
+    assert {:ok, %{nodes: nodes} = flat} =
+             Html5ever.flat_parse("<ul><li>One</li><li>Two</li></ul>")
+
+    assert [ul] = for {_id, %{name: "ul"} = node} <- nodes, do: node
+    assert [_first, second] = ul.children
+
+    nodes = Map.put(nodes, ul.id, %{ul | children: [second], attrs: [{"class", "list"}]})
+
+    assert Html5ever.flat_serialize(%{flat | nodes: nodes}, ul.id) ==
+             {:ok, ~s(<ul class="list"><li>Two</li></ul>)}
+  end
+
+  test "flat serialize with template contents and namespaces" do
+    html = ~s(<template><svg><title>Hi</title></svg></template>)
+
+    assert {:ok, %{nodes: nodes} = flat} =
+             Html5ever.flat_parse(html, template_contents: true, namespaces: true)
+
+    assert [template] = for {_id, %{name: "template"} = node} <- nodes, do: node
+
+    assert Html5ever.flat_serialize(flat, template.id) == {:ok, html}
+  end
+
+  test "flat serialize with missing nodes" do
+    assert {:ok, flat} = Html5ever.flat_parse("<p>Hello</p>")
+
+    assert {:error, %Html5ever.Error{reason: {:node_not_found, 100}}} =
+             Html5ever.flat_serialize(flat, 100)
+  end
+
+  test "flat serialize with a cycle in the children" do
+    nodes = %{
+      0 => %{id: 0, parent: nil, type: :document, children: [1]},
+      1 => %{id: 1, parent: 0, type: :element, name: "p", attrs: [], children: [0]}
+    }
+
+    assert {:error, %Html5ever.Error{reason: {:duplicated_node, 0}}} =
+             Html5ever.flat_serialize(%{nodes: nodes, root: 0})
+  end
+
+  test "parse to resource and read the nodes" do
+    html = """
+    <ul>
+      <li><a href="/one" class="first">One</a></li>
+      <li><a href="/two">Two <!-- comment --><b>2</b></a></li>
+    </ul>
+    """
+
+    assert {:ok, document} = Html5ever.parse_to_resource(html)
+    assert is_reference(document)
+
+    assert {:ok, [first, second]} = Html5ever.query(document, "a")
+
+    assert Html5ever.attributes(document, first) ==
+             {:ok, [{"href", "/one"}, {"class", "first"}]}
+
+    assert Html5ever.text(document, second) == {:ok, "Two 2"}
+
+    assert Html5ever.serialize(document, second) ==
+             {:ok, ~s(<a href="/two">Two <!-- comment --><b>2</b></a>)}
+
+    assert Html5ever.query(document, "table") == {:ok, []}
+  end
+
+  test "parse to resource uses the same IDs as the flat parse" do
+    html = "<!DOCTYPE html><p>Hello</p>"
+
+    assert {:ok, document} = Html5ever.parse_to_resource(html)
+    assert {:ok, %{nodes: nodes}} = Html5ever.flat_parse(html)
+
+    assert {:ok, [id]} = Html5ever.query(document, "p")
+    assert %{name: "p"} = nodes[id]
+
+    assert Html5ever.serialize(document, 0) ==
+             {:ok, "<!DOCTYPE html><html><head></head><body><p>Hello</p></body></html>"}
+  end
+
+  test "parse to resource with options" do
+    html = "<div id=main><template><p>Hi</p></template></div>"
+
+    assert {:ok, document} =
+             Html5ever.parse_to_resource(html, attributes_as_maps: true, template_contents: true)
+
+    assert {:ok, [div]} = Html5ever.query(document, "div")
+    assert Html5ever.attributes(document, div) == {:ok, %{"id" => "main"}}
+    assert {:ok, [_p]} = Html5ever.query(document, "p")
+    assert Html5ever.text(document, div) == {:ok, "Hi"}
+  end
+
+  test "parse to resource with invalid nodes" do
+    assert {:ok, document} = Html5ever.parse_to_resource("<p>Hello</p>")
+
+    assert {:error, %Html5ever.Error{reason: {:node_not_found, 100}} = error} =
+             Html5ever.text(document, 100)
+
+    assert Exception.message(error) == "cannot find node with ID 100"
+
+    assert {:error, %Html5ever.Error{reason: {:not_an_element, 0}}} =
+             Html5ever.attributes(document, 0)
+  end
+
+  test "select elements from html" do
+    html = """
+    <div id="main">
+      <ul>
+        <li class="item">One</li>
+        <li class="item" lang="en-US">Two</li>
+        <li><a href="/three.pdf">Three</a></li>
+      </ul>
+      <p>First</p><span>Span</span><p>Second</p>
+    </div>
+    """
+
+    assert Html5ever.select(html, "#main li.item") ==
+             {:ok,
+              [
+                {"li", [{"class", "item"}], ["One"]},
+                {"li", [{"class", "item"}, {"lang", "en-US"}], ["Two"]}
+              ]}
+
+    assert {:ok, [{"li", [{"class", "item"}, {"lang", "en-US"}], ["Two"]}]} =
+             Html5ever.select(html, "li[lang|=en]")
+
+    assert {:ok, [{"a", [{"href", "/three.pdf"}], ["Three"]}]} =
+             Html5ever.select(html, "ul > li:nth-child(3) > a[href$='.pdf']")
+
+    assert {:ok, [{"li", [], _}]} = Html5ever.select(html, "li:has(> a)")
+    assert {:ok, [{"li", [{"class", "item"}], ["One"]}]} =
+             Html5ever.select(html, "li:not([lang]).item")
+    assert {:ok, [{"p", [], ["Second"]}]} = Html5ever.select(html, "span ~ p")
+    assert {:ok, [{"p", [], ["First"]}]} = Html5ever.select(html, "p:has(+ span)")
+    assert {:ok, [{"p", [], ["First"]}, {"p", [], ["Second"]}]} = Html5ever.select(html, "P, h1")
+    assert Html5ever.select(html, "table") == {:ok, []}
+  end
+
+  test "select elements from html with options" do
+    html = "<p class=a>One</p>\n<p>Two</p>"
+
+    assert Html5ever.select(html, "p.a", attributes_as_maps: true, source_positions: true) ==
+             {:ok, [{"p", %{"class" => "a"}, ["One"], %{line: 1}}]}
+  end
+
+  test "select elements from a resource" do
+    html = "<ul><li>One</li><li>Two</li><li>Three</li></ul>"
+
+    assert {:ok, document} = Html5ever.parse_to_resource(html)
+    assert {:ok, [first, third]} = Html5ever.select(document, "li:nth-child(odd)")
+
+    assert Html5ever.text(document, first) == {:ok, "One"}
+    assert Html5ever.text(document, third) == {:ok, "Three"}
+  end
+
+  test "select with the extreme values of nth-child" do
+    html = "<ul><li>One</li><li>Two</li><li>Three</li></ul>"
+    count = fn selector -> html |> Html5ever.select(selector) |> elem(1) |> length() end
+
+    assert count.("li:nth-child(n-9223372036854775808)") == 3
+    assert count.("li:nth-child(-n+9223372036854775807)") == 3
+    assert count.("li:nth-child(-n-9223372036854775808)") == 0
+    assert count.("li:nth-child(9223372036854775807n-9223372036854775808)") == 0
+    assert count.("li:nth-child(-9223372036854775808n+2)") == 1
+
+    assert {:error, %Html5ever.Error{reason: {:invalid_selector, _}}} =
+             Html5ever.select(html, "li:nth-child(9223372036854775808)")
+  end
+
+  test "select with an invalid selector" do
+    assert {:error, %Html5ever.Error{reason: reason, message: message}} =
+             Html5ever.select("<p>Hello</p>", "p >")
+
+    assert reason == {:invalid_selector, "expected a selector at position 3"}
+    assert message == "invalid selector: expected a selector at position 3"
+
+    assert {:error, %Html5ever.Error{reason: reason}} =
+             Html5ever.select("<p>Hello</p>", "p:hover")
+
+    assert reason == {:invalid_selector, "unsupported pseudo-class \":hover\" at position 7"}
+
+    nested = fn n -> String.duplicate(":not(", n) <> "p" <> String.duplicate(")", n) end
+
+    assert {:ok, [{"p", [], ["Hello"]}]} = Html5ever.select("<p>Hello</p>", nested.(32))
+
+    assert {:error, %Html5ever.Error{reason: reason}} =
+             Html5ever.select("<p>Hello</p>", nested.(33))
+
+    assert reason ==
+             {:invalid_selector, "pseudo-classes nested more than 32 times at position 165"}
+  end
+
+  test "evaluate xpath expressions on html" do
+    html =
+      "<title>Links</title><ul><li>One</li><li class=x>Two</li></ul>" <>
+        "<a href='/a.pdf'>A</a><a href='/b.html'>B</a>"
+
+    assert Html5ever.xpath(html, "//li[2]/text()") == {:ok, ["Two"]}
+    assert Html5ever.xpath(html, "//LI[@class='x']") == {:ok, [{"li", [{"class", "x"}], ["Two"]}]}
+    assert Html5ever.xpath(html, "//a[contains(@href, 'pdf')]/@href") ==
+             {:ok, [{"href", "/a.pdf"}]}
+
+    assert Html5ever.xpath(html, "//li[1]/following::a[position() = last()]") ==
+             {:ok, [{"a", [{"href", "/b.html"}], ["B"]}]}
+
+    assert Html5ever.xpath(html, "count(//li) + 1") == {:ok, 3.0}
+    assert Html5ever.xpath(html, "string(//title)") == {:ok, "Links"}
+    assert Html5ever.xpath(html, "normalize-space(concat(' a ', '  b'))") == {:ok, "a b"}
+    assert Html5ever.xpath(html, "//li = 'Two' and not(//table)") == {:ok, true}
+    assert Html5ever.xpath(html, "0 div 0") == {:ok, :nan}
+    assert Html5ever.xpath(html, "-1 div 0") == {:ok, :neg_infinity}
+  end
+
+  test "evaluate xpath expressions on html with options" do
+    html = "<p class=a>One</p>\n<template><p>Two</p></template>"
+
+    assert Html5ever.xpath(html, "//p", attributes_as_maps: true, template_contents: true) ==
+             {:ok, [{"p", %{"class" => "a"}, ["One"]}, {"p", %{}, ["Two"]}]}
+  end
+
+  test "evaluate xpath expressions on a resource" do
+    html = "<ul><li>One</li><li lang=en>Two</li></ul>"
+
+    assert {:ok, document} = Html5ever.parse_to_resource(html)
+    assert {:ok, [first, second]} = Html5ever.xpath(document, "//li")
+    assert Html5ever.text(document, first) == {:ok, "One"}
+    assert Html5ever.xpath(document, "//li/@lang") == {:ok, [{second, "lang"}]}
+    assert Html5ever.xpath(document, "count(//li)") == {:ok, 2.0}
+  end
+
+  test "evaluate an invalid xpath expression" do
+    assert {:error, %Html5ever.Error{reason: reason, message: message}} =
+             Html5ever.xpath("<p>Hello</p>", "//p[")
+
+    assert reason == {:invalid_xpath, "expected an expression at position 4"}
+    assert message == "invalid XPath expression: expected an expression at position 4"
+
+    assert {:error, %Html5ever.Error{reason: reason}} = Html5ever.xpath("<p>Hello</p>", "$p")
+    assert reason == {:invalid_xpath, "variables are not supported at position 0"}
+
+    assert {:error, %Html5ever.Error{reason: reason}} = Html5ever.xpath("<p>Hello</p>", "1 | //p")
+    assert reason == {:invalid_xpath, "expected a node-set"}
+
+    nested = fn n -> String.duplicate("(", n) <> "1" <> String.duplicate(")", n) end
+
+    assert Html5ever.xpath("<p>Hello</p>", nested.(32)) == {:ok, 1.0}
+
+    assert {:error, %Html5ever.Error{reason: reason}} =
+             Html5ever.xpath("<p>Hello</p>", nested.(33))
+
+    assert reason == {:invalid_xpath, "expressions nested more than 32 times at position 33"}
+
+    assert Html5ever.xpath("<p>Hello</p>", "1" <> String.duplicate("+1", 32)) == {:ok, 33.0}
+
+    assert {:error, %Html5ever.Error{reason: reason}} =
+             Html5ever.xpath("<p>Hello</p>", "1" <> String.duplicate("+1", 100_000))
+
+    assert reason == {:invalid_xpath, "expressions nested more than 32 times at position 66"}
+
+    assert {:error, %Html5ever.Error{reason: reason}} =
+             Html5ever.xpath("<p>Hello</p>", "//p" <> String.duplicate("|//p", 100_000))
+
+    assert reason == {:invalid_xpath, "expressions nested more than 32 times at position 132"}
+  end
+
+  test "extract the visible text of html" do
+    html = """
+    <html>
+    <head><title>Title</title><style>p { color: red; }</style></head>
+    <body>
+      <h1>Hello   <em>big</em>
+        world</h1>
+      <!-- a comment -->
+      <p>First paragraph</p><p>Second<br>line</p>
+      <script>alert("hidden")</script>
+      <ul><li>One</li><li> Two </li></ul>
+      <pre>  keep
+      this</pre>
+      <table><tr><td>1</td><td>2</td></tr></table>
+      <template><p>Template</p></template>
+    </body>
+    </html>
+    """
+
+    assert Html5ever.text_content(html) ==
+             {:ok,
+              "Hello big world\n\nFirst paragraph\n\nSecond\nline\n\nOne\nTwo\n" <>
+                "  keep\n  this\n1\t2"}
+  end
+
+  test "extract the visible text of a resource" do
+    html = "<div><p>One</p><p>Two <b>and</b> three</p></div>"
+
+    assert {:ok, document} = Html5ever.parse_to_resource(html)
+    assert Html5ever.text_content(document) == {:ok, "One\n\nTwo and three"}
+
+    assert {:ok, [_first, second]} = Html5ever.query(document, "p")
+    assert Html5ever.text_content(document, second) == {:ok, "Two and three"}
+    assert {:error, %Html5ever.Error{reason: {:node_not_found, 100}}} =
+             Html5ever.text_content(document, 100)
+  end
+
+  test "sanitize html with the default policy" do
+    html = """
+    <p class="intro" onclick="steal()">Hello <a href="https://a.com" target="_top">world</a></p>
+    <a href=" JaVaScRiPt:steal()">link</a><a href="/relative">relative</a>
+    <section><article>unwrapped</article></section><!-- comment -->
+    <script>steal()</script><style>p { color: red; }</style>
+    """
+
+    assert Html5ever.sanitize(html) ==
+             {:ok,
+              """
+              <p>Hello <a href="https://a.com">world</a></p>
+              <a>link</a><a href="/relative">relative</a>
+              unwrapped
+
+              """}
+  end
+
+  test "sanitize html with a custom policy" do
+    html = "<div class=a id=b><img src=a.png srcset='b.png, data:x 2x'><!-- c --><em>x</em></div>"
+
+    policy = [
+      tags: ["div", "img"],
+      attributes: %{"*" => ["class"], "img" => ["src", "srcset"]},
+      url_schemes: ["https"],
+      comments: true,
+      drop_content: ["em"]
+    ]
+
+    assert Html5ever.sanitize(html, policy) ==
+             {:ok, ~s(<div class="a"><img src="a.png"><!-- c --></div>)}
+  end
+
+  test "sanitize html to a tree" do
+    html = "<p title=t>Hello <b>world</b><script>steal()</script></p><?pi?>"
+
+    assert Html5ever.sanitize_to_tree(html) ==
+             {:ok, [{"p", [], ["Hello ", {"b", [], ["world"]}]}]}
+
+    policy = [attributes: %{"p" => ["title"]}]
+
+    assert Html5ever.sanitize_to_tree(html, policy, attributes_as_maps: true) ==
+             {:ok, [{"p", %{"title" => "t"}, ["Hello ", {"b", %{}, ["world"]}]}]}
+  end
+
+  test "parse a document in chunks" do
+    html = "<!doctype html><p class=a>Caf\u00E9 \u{1F600}</p>"
+    # The chunks split the two characters that are not ASCII.
+    chunks = [binary_part(html, 0, 30), binary_part(html, 30, 4), binary_part(html, 34, 6)]
+
+    assert {:ok, parser} = Html5ever.parser_new()
+    assert Enum.all?(chunks, &(Html5ever.parser_feed(parser, &1) == :ok))
+    assert Html5ever.parser_finish(parser) == Html5ever.parse(html)
+
+    assert {:error, %Html5ever.Error{reason: :parser_finished} = error} =
+             Html5ever.parser_finish(parser)
+
+    assert Exception.message(error) == "the parser is already finished"
+
+    assert {:error, %Html5ever.Error{reason: :parser_finished}} =
+             Html5ever.parser_feed(parser, "<p>")
+  end
+
+  test "parse a document in chunks with options" do
+    assert {:ok, parser} = Html5ever.parser_new(detect_encoding: true, parse_errors: true)
+    assert :ok = Html5ever.parser_feed(parser, "<meta charset=shift_jis><p>")
+    assert :ok = Html5ever.parser_feed(parser, <<0x82, 0xA0, "</p>">>)
+
+    assert {:ok, tree, %{encoding: "Shift_JIS", errors: [_ | _]}} =
+             Html5ever.parser_finish(parser)
+
+    assert [{"html", [], [_head, {"body", [], [{"p", [], ["\u3042"]}]}]}] = tree
+  end
+
+  test "parse a chunk with invalid UTF-8" do
+    assert {:ok, parser} = Html5ever.parser_new()
+    assert :ok = Html5ever.parser_feed(parser, "<p>a")
+
+    # The position is counted from the start of the document.
+    assert {:error, %Html5ever.Error{reason: {:invalid_utf8, 4, 1}}} =
+             Html5ever.parser_feed(parser, <<0xFF>>)
+
+    assert :ok = Html5ever.parser_feed(parser, "b</p>")
+
+    assert Html5ever.parser_finish(parser) ==
+             {:ok, [{"html", [], [{"head", [], []}, {"body", [], [{"p", [], ["ab"]}]}]}]}
+  end
+
+  test "parse with limits" do
+    html = "<div><p a=1 b=2 c=3>Hello</p></div>"
+
+    assert {:ok, _} = Html5ever.parse(html, max_nodes: 6, max_depth: 5)
+    assert {:ok, _} = Html5ever.parse(html, max_attributes_per_element: 3, max_input_bytes: 35)
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_nodes, 5}} = error} =
+             Html5ever.parse(html, max_nodes: 5)
+
+    assert Exception.message(error) == "limit exceeded: max_nodes is 5"
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_depth, 4}}} =
+             Html5ever.flat_parse(html, max_depth: 4)
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_attributes_per_element, 2}}} =
+             Html5ever.parse_fragment(html, "body", max_attributes_per_element: 2)
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_input_bytes, 34}}} =
+             Html5ever.parse_to_resource(html, max_input_bytes: 34)
+  end
+
+  test "parse a deeply nested document with limits" do
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_nodes, 10_000}}} =
+             Html5ever.parse(String.duplicate("<span>", 1_000_000), max_nodes: 10_000)
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_depth, 100}}} =
+             Html5ever.flat_parse(String.duplicate("<span>", 10_000), max_depth: 100)
+
+    html = "<template><p>Hello</p></template>"
+
+    assert {:ok, _} = Html5ever.parse(html, max_depth: 5)
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_depth, 4}}} =
+             Html5ever.parse(html, max_depth: 4)
+
+    html = "<div><p>Hello</p></div>"
+
+    assert {:ok, _} = Html5ever.parse_fragment(html, "body", max_depth: 3)
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_depth, 2}}} =
+             Html5ever.parse_fragment(html, "body", max_depth: 2)
+  end
+
+  test "parse in chunks with limits" do
+    assert {:ok, parser} = Html5ever.parser_new(max_input_bytes: 10)
+    assert :ok = Html5ever.parser_feed(parser, "<p>a</p>")
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_input_bytes, 10}}} =
+             Html5ever.parser_feed(parser, "<p>b</p>")
+
+    assert Html5ever.parser_finish(parser) == Html5ever.parse("<p>a</p>")
+
+    assert {:ok, parser} = Html5ever.parser_new(max_nodes: 4)
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_nodes, 4}}} =
+             Html5ever.parser_feed(parser, "<p>a</p>")
+  end
+
+  test "tokenize html" do
+    html =
+      "<!DOCTYPE html><table><tr><td>a<b>b</i></td></table>" <>
+        "<script>if (a<b) {}</script><textarea>&amp;<p></textarea><br/><!-- c -->"
+
+    assert Html5ever.tokenize(html) ==
+             {:ok,
+              [
+                {:doctype, "html", nil, nil, false},
+                {:start_tag, "table", [], false},
+                {:start_tag, "tr", [], false},
+                {:start_tag, "td", [], false},
+                {:characters, "a"},
+                {:start_tag, "b", [], false},
+                {:characters, "b"},
+                {:end_tag, "i"},
+                {:end_tag, "td"},
+                {:end_tag, "table"},
+                {:start_tag, "script", [], false},
+                {:characters, "if (a<b) {}"},
+                {:end_tag, "script"},
+                {:start_tag, "textarea", [], false},
+                {:characters, "&<p>"},
+                {:end_tag, "textarea"},
+                {:start_tag, "br", [], true},
+                {:comment, " c "},
+                :eof
+              ]}
+  end
+
+  test "tokenize html with options" do
+    assert {:ok, tokens} =
+             Html5ever.tokenize("<p id=a id=b>Hi\u0000</p>",
+               attributes_as_maps: true,
+               parse_errors: true
+             )
+
+    assert [
+             {:parse_error, %{line: 1, message: _}},
+             {:start_tag, "p", %{"id" => "a"}, false},
+             {:characters, "Hi"},
+             {:parse_error, %{line: 1, message: _}},
+             {:characters, "\0"},
+             {:end_tag, "p"},
+             :eof
+           ] = tokens
+
+    assert {:error, %Html5ever.Error{reason: {:invalid_utf8, 0, 1}}} =
+             Html5ever.tokenize(<<0xFF>>)
+  end
+
+  test "tokenize foreign content" do
+    html = "<svg><style><b></b></style><![CDATA[x<y]]></svg><style><b></b></style>"
+
+    assert Html5ever.tokenize(html) ==
+             {:ok,
+              [
+                {:start_tag, "svg", [], false},
+                {:start_tag, "style", [], false},
+                {:start_tag, "b", [], false},
+                {:end_tag, "b"},
+                {:end_tag, "style"},
+                {:characters, "x<y"},
+                {:end_tag, "svg"},
+                {:start_tag, "style", [], false},
+                {:characters, "<b></b>"},
+                {:end_tag, "style"},
+                :eof
+              ]}
+  end
+
+  test "parse xml" do
+    xml = """
+    <?xml version="1.0" encoding="UTF-8"?>
+    <?xml-stylesheet href="feed.xsl"?>
+    <rss xmlns:atom="http://www.w3.org/2005/Atom">
+    <channel><atom:link href="/feed"/><title>A &amp; B</title>
+    <item><description><![CDATA[<p>Hi</p>]]></description><!-- note --></item>
+    </channel></rss>
+    """
+
+    atom = "http://www.w3.org/2005/Atom"
+
+    assert Html5ever.parse_xml(xml, namespaces: true) ==
+             {:ok,
+              [
+                {:pi, "xml", ~s(version="1.0" encoding="UTF-8")},
+                {:pi, "xml-stylesheet", ~s(href="feed.xsl")},
+                {{nil, "rss"}, [],
+                 [
+                   "\n",
+                   {{nil, "channel"}, [],
+                    [
+                      {{atom, "link"}, [{"href", "/feed"}], []},
+                      {{nil, "title"}, [], ["A & B"]},
+                      "\n",
+                      {{nil, "item"}, [],
+                       [{{nil, "description"}, [], ["<p>Hi</p>"]}, {:comment, " note "}]},
+                      "\n"
+                    ]}
+                 ]}
+              ]}
+  end
+
+  test "flat parse xml" do
+    xml = ~s(<!DOCTYPE note SYSTEM "note.dtd"><note lang="en"><to>Ana</to></note>)
+
+    assert {:ok, %{nodes: nodes, root: 0}} = Html5ever.flat_parse_xml(xml)
+
+    assert [doctype] = for {_id, %{type: :doctype} = node} <- nodes, do: node
+    assert %{name: "note", public_id: "", system_id: "note.dtd"} = doctype
+
+    assert [note] = for {_id, %{name: "note"} = node} <- nodes, do: node
+    assert %{attrs: [{"lang", "en"}], children: [to]} = note
+    assert %{name: "to", children: [text]} = nodes[to]
+    assert %{type: :text, contents: "Ana"} = nodes[text]
+  end
+
+  test "parse invalid xml" do
+    assert {:ok, tree, %{errors: [_ | _]}} = Html5ever.parse_xml("<a><b></a>", parse_errors: true)
+    assert [{"a", [], [{"b", [], []}]}] = tree
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_nodes, 1}}} =
+             Html5ever.parse_xml("<a><b></b></a>", max_nodes: 1)
+  end
+
+  test "rewrite attributes" do
+    html = ~s(<a href="/a" ping="/track">A</a><a href="/b">B</a>)
+
+    assert {:ok, result} =
+             Html5ever.rewrite(html, [
+               {"a[ping]", [{:remove_attribute, "ping"}]},
+               {"a", [{:set_attribute, "REL", "noopener"}, {:set_attribute, "href", "#"}]}
+             ])
+
+    assert result == ~s(<a href="#" rel="noopener">A</a><a href="#" rel="noopener">B</a>)
+  end
+
+  test "rewrite contents" do
+    html = ~s(<p class="x">Hi</p><ul><li>1</li></ul><script>track()</script>)
+
+    assert {:ok, result} =
+             Html5ever.rewrite(html, [
+               {"p", [{:set_inner_text, "<b>&</b>"}]},
+               {"ul", [{:set_inner_html, "<li>a<li>b"}]},
+               {"script", [:remove]}
+             ])
+
+    assert result == ~s(<p class="x">&lt;b&gt;&amp;&lt;/b&gt;</p><ul><li>a<li>b</ul>)
+  end
+
+  test "rewrite with prepend and append" do
+    html = ~s(<ul><li>2</li></ul><table><tr><td>1</td></tr></table>)
+
+    assert {:ok, result} =
+             Html5ever.rewrite(html, [
+               {"ul", [{:prepend, "<li>1</li>"}, {:append, "<li>3</li>"}]},
+               {"tr", [{:append, "<td>2</td>"}]}
+             ])
+
+    assert result ==
+             ~s(<ul><li>1</li><li>2</li><li>3</li></ul>) <>
+               ~s(<table><tr><td>1</td><td>2</td></tr></table>)
+  end
+
+  test "rewrite does not match inserted content" do
+    html = ~s(<div><p>A</p></div>)
+
+    assert {:ok, result} =
+             Html5ever.rewrite(html, [
+               {"div", [{:append, "<div><p>B</p></div>"}]},
+               {"p", [{:set_attribute, "class", "seen"}]}
+             ])
+
+    assert result == ~s(<div><p class="seen">A</p><div><p>B</p></div></div>)
+  end
+
+  test "rewrite keeps the source that is not changed" do
+    html = """
+    <!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">
+    <TITLE>Old</TITLE>
+    <!-- c --><P class=a>One<P>Two &amp; <img src=x.png>
+    """
+
+    assert Html5ever.rewrite(html, []) == {:ok, html}
+
+    assert {:ok, result} =
+             Html5ever.rewrite(html, [
+               {"p.a", [{:set_attribute, "id", "first"}]},
+               {"img", [{:set_attribute, "alt", ""}]}
+             ])
+
+    assert result == """
+           <!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">
+           <TITLE>Old</TITLE>
+           <!-- c --><p class="a" id="first">One<P>Two &amp; <img src="x.png" alt="">
+           """
+  end
+
+  test "rewrite with implied end tags" do
+    html = "<ul><li>1<li>2</ul><p>a<div>b</div>"
+
+    assert {:ok, result} =
+             Html5ever.rewrite(html, [
+               {"li + li", [{:set_inner_html, "<i>two</i>"}]},
+               {"p", [{:append, "!"}]}
+             ])
+
+    assert result == "<ul><li>1<li><i>two</i></ul><p>a!<div>b</div>"
+  end
+
+  test "rewrite svg" do
+    html = ~s(<svg viewBox="0 0 10 10"><g><circle r=1 /></g></svg><p>x</p>)
+
+    assert {:ok, result} =
+             Html5ever.rewrite(html, [
+               {"g", [{:append, ~s(<rect width="1"/>)}]},
+               {"svg circle", [{:set_attribute, "fill", "red"}]}
+             ])
+
+    assert result ==
+             ~s(<svg viewBox="0 0 10 10"><g><circle r="1" fill="red" /><rect width="1"/></g>) <>
+               ~s(</svg><p>x</p>)
+  end
+
+  test "rewrite with invalid rules" do
+    assert {:error, %Html5ever.Error{reason: {:invalid_selector, _}}} =
+             Html5ever.rewrite("<p>Hi</p>", [{"p[", [:remove]}])
+
+    assert {:error, %Html5ever.Error{reason: {:invalid_selector, _}}} =
+             Html5ever.rewrite("<p>Hi</p>", [{"p:has(a)", [:remove]}])
+
+    assert_raise ArgumentError, fn ->
+      Html5ever.rewrite("<p>Hi</p>", [{"p", [:unknown]}])
+    end
+  end
+
+  test "extract links" do
+    html = """
+    <head>
+      <base href="/docs/">
+      <meta http-equiv="Refresh" content="1; url=../next">
+      <link rel="stylesheet" href="style.css">
+    </head>
+    <a href="page?q=1#top">Page</a>
+    <a href=" https://other.org/ ">Other</a>
+    <a href="mailto:ana@example.com">Mail</a>
+    <a href="http://[bad">Bad</a>
+    <img src="a.png" srcset="a.png 1x, /b.png 2x,c.png, data:image/png;base64,AAA= 3x">
+    <div style="background: url('bg.png')"></div>
+    <form action=""><button formaction="send">Send</button></form>
+    <script src="//cdn.example.com/app.js"></script>
+    """
+
+    assert {:ok, links} = Html5ever.extract_links(html, "https://example.com/site/index.html")
+
+    assert Enum.map(links, &{&1.element, &1.attribute, &1.url}) == [
+             {"meta", "content", "https://example.com/next"},
+             {"link", "href", "https://example.com/docs/style.css"},
+             {"a", "href", "https://example.com/docs/page?q=1#top"},
+             {"a", "href", "https://other.org/"},
+             {"a", "href", "mailto:ana@example.com"},
+             {"img", "src", "https://example.com/docs/a.png"},
+             {"img", "srcset", "https://example.com/docs/a.png"},
+             {"img", "srcset", "https://example.com/b.png"},
+             {"img", "srcset", "https://example.com/docs/c.png"},
+             {"img", "srcset", "data:image/png;base64,AAA="},
+             {"div", "style", "https://example.com/docs/bg.png"},
+             {"form", "action", "https://example.com/docs/"},
+             {"button", "formaction", "https://example.com/docs/send"},
+             {"script", "src", "https://cdn.example.com/app.js"}
+           ]
+  end
+
+  test "extract links with template contents" do
+    html = ~s(<template><a href="/a">A</a></template>)
+
+    assert {:ok, []} = Html5ever.extract_links(html, "https://example.com/")
+
+    assert {:ok, [%{url: "https://example.com/a"}]} =
+             Html5ever.extract_links(html, "https://example.com/", template_contents: true)
+  end
+
+  test "extract links with an invalid base url" do
+    assert {:error, %Html5ever.Error{reason: {:invalid_url, "/relative"}}} =
+             Html5ever.extract_links(~s(<a href="/a">A</a>), "/relative")
+  end
+
+  test "metadata" do
+    html = """
+    <!DOCTYPE html>
+    <html lang="pt-BR">
+    <head>
+      <meta http-equiv="Content-Type" content="text/html; charset=ISO-8859-1">
+      <title>
+        Hello   &amp; <b>world</b>
+      </title>
+      <meta name="Description" content="A page">
+      <meta property="og:image" content="/a.png">
+      <meta property="og:image" content="/b.png">
+      <meta name="twitter:card" content="summary">
+      <meta name="empty">
+      <link rel="alternate canonical" href="/first">
+      <link rel="canonical" href="/second">
+      <script type="application/ld+json">{"@type": "Article"}</script>
+    </head>
+    <body>
+      <svg><title>Icon</title></svg>
+      <script type="application/LD+JSON">{"@type": "Person"}</script>
+    </body>
+    </html>
+    """
+
+    assert Html5ever.metadata(html) ==
+             {:ok,
+              %{
+                title: "Hello & <b>world</b>",
+                lang: "pt-BR",
+                charset: "windows-1252",
+                canonical: "/first",
+                meta: [
+                  {"description", "A page"},
+                  {"og:image", "/a.png"},
+                  {"og:image", "/b.png"},
+                  {"twitter:card", "summary"}
+                ],
+                json_ld: [~s({"@type": "Article"}), ~s({"@type": "Person"})]
+              }}
+  end
+
+  test "metadata of a document without it" do
+    assert Html5ever.metadata("<p>Hello</p>") ==
+             {:ok, %{title: nil, lang: nil, charset: nil, canonical: nil, meta: [], json_ld: []}}
+
+    assert {:error, %Html5ever.Error{reason: {:limit_exceeded, :max_nodes, 2}}} =
+             Html5ever.metadata("<p>Hello</p>", max_nodes: 2)
+  end
 end